@@ -0,0 +1,133 @@
+use bevy::{
+    core_pipeline::{clear_color::ClearColorConfig, tonemapping::Tonemapping, Skybox},
+    prelude::*,
+    render::camera::RenderTarget,
+};
+use bevy_xr::{head::XrEye, window::XrWindow, XrLocal};
+
+use crate::{WebXrClearColor, WebXrEnvironmentBlendMode, XrSecondaryView};
+
+///
+/// What passthrough replaced on a camera, to restore it once the environment is opaque again.
+///
+#[derive(Component)]
+pub(crate) struct PassthroughBackup {
+    tonemapping: Option<Tonemapping>,
+    skybox: Option<Skybox>,
+}
+
+pub(crate) fn is_passthrough(blend_mode: Option<&WebXrEnvironmentBlendMode>) -> bool {
+    matches!(
+        blend_mode,
+        Some(WebXrEnvironmentBlendMode::Additive | WebXrEnvironmentBlendMode::AlphaBlend)
+    )
+}
+
+///
+/// How a view clears its render target, depending on whether it's the first active view of the target.
+/// Also used when the views are spawned, so they clear correctly in their first frame.
+///
+pub(crate) fn view_clear_color(
+    clear_color: &WebXrClearColor,
+    passthrough: bool,
+    first: bool,
+) -> ClearColorConfig {
+    if !first {
+        ClearColorConfig::None
+    } else if passthrough {
+        ClearColorConfig::Custom(Color::NONE)
+    } else {
+        clear_color.0.clone()
+    }
+}
+
+fn same_clear_color(a: &ClearColorConfig, b: &ClearColorConfig) -> bool {
+    match (a, b) {
+        (ClearColorConfig::Default, ClearColorConfig::Default)
+        | (ClearColorConfig::None, ClearColorConfig::None) => true,
+        (ClearColorConfig::Custom(a), ClearColorConfig::Custom(b)) => a == b,
+        _ => false,
+    }
+}
+
+///
+/// Views share their render target, so only the first active view of a target clears it and the others load it.
+/// See-through displays get transparent views without skybox and tonemapping, which would cover the environment.
+///
+pub(crate) fn update_xr_clear_color(
+    clear_color: Res<WebXrClearColor>,
+    blend_mode: Option<Res<WebXrEnvironmentBlendMode>>,
+    mut cameras: Query<
+        (
+            Entity,
+            &Camera,
+            &mut Camera3d,
+            Option<&mut Tonemapping>,
+            Option<&Skybox>,
+            Option<&PassthroughBackup>,
+        ),
+        (
+            With<XrLocal>,
+            Or<(With<XrEye>, With<XrWindow>, With<XrSecondaryView>)>,
+        ),
+    >,
+    mut commands: Commands,
+) {
+    let passthrough = is_passthrough(blend_mode.as_deref());
+
+    let mut order: Vec<(isize, Entity, bool)> = cameras
+        .iter()
+        .filter(|(_, camera, ..)| camera.is_active)
+        .map(|(entity, camera, ..)| {
+            (
+                camera.order,
+                entity,
+                matches!(camera.target, RenderTarget::Window(_)),
+            )
+        })
+        .collect();
+    order.sort();
+
+    let mut cleared_framebuffer = false;
+    let mut cleared_window = false;
+
+    for (_, entity, window) in order {
+        let Ok((_, _, mut camera_3d, tonemapping, skybox, backup)) = cameras.get_mut(entity) else {
+            continue;
+        };
+
+        let cleared = if window {
+            &mut cleared_window
+        } else {
+            &mut cleared_framebuffer
+        };
+
+        let view_clear_color = view_clear_color(&clear_color, passthrough, !*cleared);
+        // Only assigned when it differs, as writing it would mark the camera as changed every frame.
+        if !same_clear_color(&camera_3d.clear_color, &view_clear_color) {
+            camera_3d.clear_color = view_clear_color;
+        }
+        *cleared = true;
+
+        if passthrough && backup.is_none() {
+            commands.entity(entity).insert(PassthroughBackup {
+                tonemapping: tonemapping.as_deref().copied(),
+                skybox: skybox.cloned(),
+            });
+            commands.entity(entity).remove::<Skybox>();
+            if let Some(mut tonemapping) = tonemapping {
+                *tonemapping = Tonemapping::None;
+            }
+        } else if !passthrough {
+            if let Some(backup) = backup {
+                if let (Some(mut tonemapping), Some(backup)) = (tonemapping, backup.tonemapping) {
+                    *tonemapping = backup;
+                }
+                if let Some(skybox) = backup.skybox.clone() {
+                    commands.entity(entity).insert(skybox);
+                }
+                commands.entity(entity).remove::<PassthroughBackup>();
+            }
+        }
+    }
+}
@@ -10,10 +10,10 @@ pub fn dom_point_to_vec3(point: &DomPointReadOnly) -> Vec3 {
 }
 
 pub fn dom_point_to_quat(point: &DomPointReadOnly) -> Quat {
-    Quat {
-        x: point.x() as f32,
-        y: point.y() as f32,
-        z: point.z() as f32,
-        w: point.w() as f32,
-    }
+    Quat::from_xyzw(
+        point.x() as f32,
+        point.y() as f32,
+        point.z() as f32,
+        point.w() as f32,
+    )
 }
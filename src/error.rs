@@ -1,5 +1,5 @@
 use thiserror::Error;
-use wasm_bindgen::JsValue;
+use wasm_bindgen::{JsCast, JsValue};
 
 #[derive(Debug, Error, Clone)]
 pub enum WebXrError {
@@ -15,6 +15,8 @@ pub enum WebXrError {
     WebGl2ContextNotFound,
     #[error("Session lost!")]
     SessionError,
+    #[error("Another session is starting or ending!")]
+    SessionBusy,
     #[error("Browser window not found!")]
     NoWindow,
     #[error("HTML Document not found!")]
@@ -30,3 +32,80 @@ pub enum WebXrError {
     #[error("Element is not a html canvas element.")]
     ElementNotCanvasElement(web_sys::Element),
 }
+
+///
+/// A copy of WebXrError that can be sent in events, as js values can't be sent between threads.
+/// The js values are replaced by their messages, e.g. the exception of a denied session request.
+///
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum WebXrSessionError {
+    #[error("Some js error: {0}")]
+    JsError(String),
+    #[error("Failed to get bool from JsValue!")]
+    NotABool,
+    #[error("WebXr currently not supported!")]
+    NotSupported,
+    #[error("Canvas couldn't be found!")]
+    CanvasNotFound,
+    #[error("WebGL2 context not found!")]
+    WebGl2ContextNotFound,
+    #[error("Session lost!")]
+    SessionError,
+    #[error("Another session is starting or ending!")]
+    SessionBusy,
+    #[error("Browser window not found!")]
+    NoWindow,
+    #[error("HTML Document not found!")]
+    NoDocument,
+    /// The tag name of the element.
+    #[error("Element is not a html button element: {0}")]
+    ElementNotButtonElement(String),
+    #[error("HTML Body not found!")]
+    NoBody,
+    #[error("Failed to request xr session: {0}")]
+    SessionRequestError(String),
+    #[error("HTML Canvas not found!")]
+    NoCanvas,
+    /// The tag name of the element.
+    #[error("Element is not a html canvas element: {0}")]
+    ElementNotCanvasElement(String),
+}
+
+impl From<&WebXrError> for WebXrSessionError {
+    fn from(error: &WebXrError) -> Self {
+        match error {
+            WebXrError::JsError(value) => WebXrSessionError::JsError(js_message(value)),
+            WebXrError::NotABool => WebXrSessionError::NotABool,
+            WebXrError::NotSupported => WebXrSessionError::NotSupported,
+            WebXrError::CanvasNotFound => WebXrSessionError::CanvasNotFound,
+            WebXrError::WebGl2ContextNotFound => WebXrSessionError::WebGl2ContextNotFound,
+            WebXrError::SessionError => WebXrSessionError::SessionError,
+            WebXrError::SessionBusy => WebXrSessionError::SessionBusy,
+            WebXrError::NoWindow => WebXrSessionError::NoWindow,
+            WebXrError::NoDocument => WebXrSessionError::NoDocument,
+            WebXrError::ElementNotButtonElement(element) => {
+                WebXrSessionError::ElementNotButtonElement(element.tag_name())
+            }
+            WebXrError::NoBody => WebXrSessionError::NoBody,
+            WebXrError::SessionRequestError(value) => {
+                WebXrSessionError::SessionRequestError(js_message(value))
+            }
+            WebXrError::NoCanvas => WebXrSessionError::NoCanvas,
+            WebXrError::ElementNotCanvasElement(element) => {
+                WebXrSessionError::ElementNotCanvasElement(element.tag_name())
+            }
+        }
+    }
+}
+
+/// The name and message of js exceptions, e.g. "NotAllowedError: The user denied permission", or the value itself.
+fn js_message(value: &JsValue) -> String {
+    match value.dyn_ref::<js_sys::Error>() {
+        Some(exception) => format!(
+            "{}: {}",
+            String::from(exception.name()),
+            String::from(exception.message())
+        ),
+        None => format!("{:?}", value),
+    }
+}
@@ -1,10 +1,29 @@
 use bevy::prelude::*;
 use bevy_xr::space::XrOrigin;
 
-use crate::XrMode;
+use crate::{
+    error::{WebXrError, WebXrSessionError},
+    XrMode,
+};
 
 pub(crate) fn add_events(app: &mut App) {
+    app.add_event::<WebXrCommand>();
     app.add_event::<WebXrSessionInitialized>();
+    app.add_event::<WebXrFrameRateChanged>();
+    app.add_event::<WebXrSessionEnded>();
+    app.add_event::<WebXrSessionFailed>();
+    app.add_event::<WebXrVisibilityChanged>();
+}
+
+///
+/// Enters or exits a session from within the app, e.g. from an in-app "Enter VR" button.
+/// Browsers only allow entering immersive sessions shortly after a user activation like a click.
+/// Entering a mode that isn't supported or entering while another session starts or ends sends a WebXrSessionFailed.
+///
+#[derive(Event, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WebXrCommand {
+    Enter(XrMode),
+    Exit,
 }
 
 #[derive(Event)]
@@ -12,3 +31,44 @@ pub struct WebXrSessionInitialized {
     pub mode: XrMode,
     pub origin: XrOrigin,
 }
+
+#[derive(Event)]
+pub struct WebXrSessionEnded {
+    pub mode: XrMode,
+}
+
+/// Sent when a session could not be started. The app keeps running in the window.
+#[derive(Event, Clone, Debug)]
+pub struct WebXrSessionFailed {
+    pub mode: XrMode,
+    /// Tells e.g. an unsupported mode apart from a denied permission.
+    pub error: WebXrSessionError,
+    /// The error including the js exception's message, e.g. to show it to the user.
+    pub message: String,
+}
+
+impl WebXrSessionFailed {
+    pub(crate) fn new(mode: XrMode, error: &WebXrError) -> WebXrSessionFailed {
+        let error = WebXrSessionError::from(error);
+
+        WebXrSessionFailed {
+            mode,
+            message: error.to_string(),
+            error,
+        }
+    }
+}
+
+/// Sent when the visibility state of the running session changes, e.g. when the user opens the system menu or takes off the headset.
+#[derive(Event, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WebXrVisibilityChanged {
+    Visible,
+    VisibleBlurred,
+    Hidden,
+}
+
+/// Sent when the browser changed the refresh rate of the running session, e.g. after WebXrFrameRate::target_frame_rate was set.
+#[derive(Event, Clone, Copy, Debug, PartialEq)]
+pub struct WebXrFrameRateChanged {
+    pub frame_rate: f32,
+}
@@ -1,68 +1,381 @@
 use crate::{
-    error::WebXrError, events::WebXrSessionInitialized, WebXrFrame, WebXrSettings, XrMode,
+    error::WebXrError,
+    events::{
+        WebXrCommand, WebXrFrameRateChanged, WebXrSessionEnded, WebXrSessionFailed,
+        WebXrSessionInitialized, WebXrVisibilityChanged,
+    },
+    state::WebXrSessionState,
+    WebXrEnabledFeatures, WebXrEnvironmentBlendMode, WebXrFrame, WebXrFrameRate, WebXrFrameTiming,
+    WebXrFramebufferEncoding, WebXrFramebufferFormat, WebXrLayerSettings, WebXrReferenceSpaceType,
+    WebXrSessionFeatures, WebXrSettings, WebXrSupport, XrMode,
 };
-use bevy::app::PluginsState;
-use bevy::{prelude::*, tasks::AsyncComputeTaskPool};
+use bevy::ecs::schedule::ScheduleLabel;
+use bevy::prelude::*;
+use bevy::render::render_resource::TextureFormat;
+use bevy::time::TimeUpdateStrategy;
+use bevy::window::RequestRedraw;
+use bevy::winit::{UpdateMode, WinitSettings};
 use bevy_xr::space::XrOrigin;
-use std::sync::{Arc, Mutex};
-use std::{cell::RefCell, rc::Rc};
-use wasm_bindgen::{prelude::Closure, JsCast, UnwrapThrowExt};
+use std::time::Duration;
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+use wasm_bindgen::{prelude::Closure, JsCast, JsValue};
 use wasm_bindgen_futures::{spawn_local, JsFuture};
 use web_sys::{
     HtmlButtonElement, HtmlCanvasElement, XrReferenceSpace, XrReferenceSpaceType, XrSession,
-    XrSessionInit, XrSessionMode,
+    XrSessionEvent, XrSessionInit, XrSessionMode, XrVisibilityState,
 };
+use winit::event_loop::{EventLoop, EventLoopProxy};
 
 ///
-/// This is central unsafe method to get winit and webxr working together in bevy with minimal upstreaming.
-///  
+/// Winit keeps running the app like it does without webxr, so window events and input keep working.
+/// Browser callbacks reach the app through the AppHandle, and xr frames wake winit up to update the app.
+///
 pub(crate) fn webxr_runner(mut app: App) {
     let settings = app
         .world
-        .remove_resource::<WebXrSettings>()
-        .expect("WebXrSettings not found!");
+        .get_resource::<WebXrSettings>()
+        .expect("WebXrSettings not found!")
+        .clone();
 
-    let app_mutex = Arc::new(Mutex::new(app));
+    let proxy = app
+        .world
+        .get_non_send_resource::<EventLoop<()>>()
+        .expect("The WebXrPlugin needs the WinitPlugin!")
+        .create_proxy();
+    let handle = AppHandle::new(proxy);
 
-    spawn_local(async {
-        initialize_webxr(settings, app_mutex).await;
-    });
+    app.world.insert_non_send_resource(handle.clone());
+    app.world.insert_non_send_resource(XrButtons::default());
+
+    spawn_local(initialize_webxr(settings, handle));
+
+    bevy::winit::winit_runner(app);
 }
 
-async fn initialize_webxr(settings: WebXrSettings, app: Arc<Mutex<App>>) {
-    let supported_sessions = get_supported_sessions().await.unwrap_throw();
-    if supported_sessions.vr & settings.vr_supported {
-        info!(
-            "Initialize vr button: {:?}",
-            initialize_button(XrButtonType::VR, settings.clone(), app.clone())
-        );
+async fn initialize_webxr(settings: WebXrSettings, handle: AppHandle) {
+    let support = match get_supported_sessions().await {
+        Ok(support) => support,
+        Err(error) => {
+            warn!("Failed to get supported sessions: {}", error);
+
+            let (vr, ar) = (settings.vr_supported, settings.ar_supported);
+            handle.run(move |world| {
+                if vr {
+                    world.send_event(WebXrSessionFailed::new(XrMode::VR, &error));
+                }
+                if ar {
+                    world.send_event(WebXrSessionFailed::new(XrMode::AR, &error));
+                }
+            });
+
+            WebXrSupport::default()
+        }
     };
-    if supported_sessions.ar & settings.ar_supported {
-        info!(
-            "Initialize ar button: {:?}",
-            initialize_button(XrButtonType::AR, settings.clone(), app.clone())
-        );
+
+    let handle_clone = handle.clone();
+    handle.run(move |world| update_support(world, support, &handle_clone));
+
+    info!(
+        "Device change listener initialized: {:?}",
+        initialize_device_change(settings.clone(), handle.clone())
+    );
+
+    if support.inline & settings.inline_supported {
+        initialize_session(XrMode::Inline, settings.clone(), handle).await;
+    }
+}
+
+///
+/// The animation loop that is currently driving the app.
+/// Winit drives it while no session is active and the xr frames drive it while their session is active.
+///
+enum AnimationLoop {
+    Window,
+    Xr(XrSession),
+}
+
+type Task = Box<dyn FnOnce(&mut World)>;
+
+///
+/// Winit owns the app, so browser callbacks queue tasks and wake winit up to run them at the start of the next update.
+///
+#[derive(Clone)]
+pub(crate) struct AppHandle(Rc<AppHandleState>);
+
+struct AppHandleState {
+    tasks: RefCell<Vec<Task>>,
+    /// Only set within the xr frame callback, as frames aren't valid outside of it.
+    frame: RefCell<Option<WebXrFrame>>,
+    animation_loop: RefCell<AnimationLoop>,
+    /// Whether winit waits for events instead of polling the window's animation frames.
+    waiting: Cell<bool>,
+    window_loop: Cell<bool>,
+    proxy: EventLoopProxy<()>,
+}
+
+impl AppHandle {
+    fn new(proxy: EventLoopProxy<()>) -> AppHandle {
+        AppHandle(Rc::new(AppHandleState {
+            tasks: RefCell::new(Vec::new()),
+            frame: RefCell::new(None),
+            animation_loop: RefCell::new(AnimationLoop::Window),
+            waiting: Cell::new(false),
+            window_loop: Cell::new(false),
+            proxy,
+        }))
+    }
+
+    fn run(&self, task: impl FnOnce(&mut World) + 'static) {
+        self.0.tasks.borrow_mut().push(Box::new(task));
+        self.wake();
+    }
+
+    ///
+    /// Resolves after the update that ran the task, or never if the app doesn't update anymore.
+    ///
+    async fn run_and_wait(&self, task: impl FnOnce(&mut World) + 'static) {
+        let (sender, receiver) = futures::channel::oneshot::channel();
+        self.run(move |world| {
+            task(world);
+            let _ = sender.send(());
+        });
+        let _ = receiver.await;
+    }
+
+    ///
+    /// Winit updates right away while it waits for events. Otherwise it updates with the next window animation frame.
+    ///
+    fn wake(&self) {
+        // Only fails once the event loop is gone.
+        let _ = self.0.proxy.send_event(());
+    }
+
+    fn session(&self) -> Option<XrSession> {
+        match &*self.0.animation_loop.borrow() {
+            AnimationLoop::Xr(session) => Some(session.clone()),
+            AnimationLoop::Window => None,
+        }
+    }
+}
+
+///
+/// Runs the tasks of the browser callbacks and hands the xr frame of this update to the app.
+///
+pub(crate) fn run_tasks(world: &mut World) {
+    let Some(handle) = world.get_non_send_resource::<AppHandle>().cloned() else {
+        return;
     };
 
-    if supported_sessions.inline {
-        initialize_session(XrMode::Inline, settings.clone(), app.clone()).await;
+    let tasks = std::mem::take(&mut *handle.0.tasks.borrow_mut());
+    for task in tasks {
+        task(world);
+    }
+
+    let xr_frame_time = world.resource::<WebXrSettings>().xr_frame_time;
+    let frame = handle.0.frame.borrow_mut().take();
+
+    if let Some(frame) = frame {
+        let timing = frame_timing(
+            frame.time,
+            &frame.webxr_frame,
+            world.get_resource::<WebXrFrameTiming>(),
+        );
+        if xr_frame_time {
+            world.insert_resource(TimeUpdateStrategy::ManualDuration(timing.interval));
+        }
+        world.insert_resource(timing);
+        world.insert_non_send_resource(frame);
     } else {
-        let app = Arc::try_unwrap(app).unwrap().into_inner().unwrap();
-        bevy::winit::winit_runner(app);
+        world.remove_non_send_resource::<WebXrFrame>();
+
+        // Updates between xr frames, e.g. for input events, don't move the time of the xr frames.
+        if xr_frame_time && handle.session().is_some() {
+            world.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::ZERO));
+        }
     }
 }
 
-struct SupportedSessions {
-    inline: bool,
-    vr: bool,
-    ar: bool,
+///
+/// Commands are handled at the end of the update they were sent in to stay within the user activation of e.g. a click.
+///
+pub(crate) fn handle_commands(
+    mut commands: EventReader<WebXrCommand>,
+    handle: Option<NonSend<AppHandle>>,
+    settings: Res<WebXrSettings>,
+    support: Option<Res<WebXrSupport>>,
+    state: Res<State<WebXrSessionState>>,
+    next_state: Res<NextState<WebXrSessionState>>,
+    mut failed: EventWriter<WebXrSessionFailed>,
+) {
+    let state = next_state.0.unwrap_or(*state.get());
+    let support = support.as_deref().copied().unwrap_or_default();
+
+    for command in commands.read() {
+        info!("Handling command: {:?}", command);
+
+        match *command {
+            WebXrCommand::Enter(mode) => {
+                let supported = match mode {
+                    XrMode::VR => support.vr & settings.vr_supported,
+                    XrMode::AR => support.ar & settings.ar_supported,
+                    XrMode::Inline => support.inline & settings.inline_supported,
+                };
+                let busy = matches!(
+                    state,
+                    WebXrSessionState::Requesting | WebXrSessionState::Ending
+                );
+
+                match handle.as_deref() {
+                    Some(handle) if supported && !busy => {
+                        spawn_local(initialize_session(mode, settings.clone(), handle.clone()))
+                    }
+                    Some(_) if supported => {
+                        failed.send(WebXrSessionFailed::new(mode, &WebXrError::SessionBusy))
+                    }
+                    // Without the webxr runner nothing can drive a session.
+                    _ => failed.send(WebXrSessionFailed::new(mode, &WebXrError::NotSupported)),
+                }
+            }
+            WebXrCommand::Exit => {
+                if let Some(handle) = handle.as_deref() {
+                    spawn_local(end_session(handle.clone()));
+                }
+            }
+        }
+    }
 }
 
-async fn get_supported_sessions() -> Result<SupportedSessions, WebXrError> {
-    let xr = web_sys::window()
-        .ok_or(WebXrError::NoWindow)?
-        .navigator()
-        .xr();
+///
+/// Runs after Last, so that the runner sees what every system of the app did in this update.
+///
+#[derive(ScheduleLabel, Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct WebXrRunnerUpdate;
+
+///
+/// While a session is requested or running, winit waits for events, so that every xr frame updates the app right away.
+/// Browsers may pause the window's animation frames during immersive sessions, so winit must not poll them then.
+/// Runs in WebXrRunnerUpdate, as it swallows the RequestRedraw events of the app meanwhile.
+///
+pub(crate) fn update_winit_settings(
+    handle: Option<NonSend<AppHandle>>,
+    state: Res<State<WebXrSessionState>>,
+    next_state: Res<NextState<WebXrSessionState>>,
+    mut winit_settings: ResMut<WinitSettings>,
+    mut redraw_requests: ResMut<Events<RequestRedraw>>,
+    mut window_update_modes: Local<Option<(UpdateMode, UpdateMode)>>,
+) {
+    let Some(handle) = handle else {
+        return;
+    };
+
+    let waiting = !matches!(
+        next_state.0.unwrap_or(*state.get()),
+        WebXrSessionState::Unsupported | WebXrSessionState::Available
+    );
+
+    if waiting {
+        if window_update_modes.is_none() {
+            *window_update_modes =
+                Some((winit_settings.focused_mode, winit_settings.unfocused_mode));
+
+            // Never waits until a timeout, so only events update the app.
+            let update_mode = UpdateMode::Reactive {
+                wait: Duration::MAX,
+            };
+            winit_settings.focused_mode = update_mode;
+            winit_settings.unfocused_mode = update_mode;
+        }
+
+        // Redraw requests make winit poll the window's animation frames. The xr frames redraw anyway.
+        // Every system of this update already ran, but readers that would only get them in the next update miss them.
+        redraw_requests.clear();
+    } else if let Some((focused_mode, unfocused_mode)) = window_update_modes.take() {
+        winit_settings.focused_mode = focused_mode;
+        winit_settings.unfocused_mode = unfocused_mode;
+    }
+
+    handle.0.waiting.set(waiting);
+
+    if waiting && handle.session().is_none() && !handle.0.window_loop.get() {
+        info!(
+            "Window animation loop initialized: {:?}",
+            request_first_window_frame(handle.clone())
+        );
+    }
+}
+
+fn set_session_state(world: &mut World, state: WebXrSessionState) {
+    world
+        .resource_mut::<NextState<WebXrSessionState>>()
+        .set(state);
+
+    update_buttons(world, state);
+}
+
+///
+/// The state including a transition that is not applied yet, as the state only changes within app.update().
+///
+fn session_state(world: &World) -> WebXrSessionState {
+    world
+        .resource::<NextState<WebXrSessionState>>()
+        .0
+        .unwrap_or(*world.resource::<State<WebXrSessionState>>().get())
+}
+
+///
+/// Keeps waking winit with the window's animation frames while it waits for a session's xr frames.
+///
+fn request_first_window_frame(handle: AppHandle) -> Result<(), WebXrError> {
+    info!("Starting window rendering!");
+
+    let window = web_sys::window().ok_or(WebXrError::NoWindow)?;
+
+    let closure: Rc<RefCell<Option<Closure<dyn FnMut(f64)>>>> = Rc::new(RefCell::new(None));
+    let closure_clone = closure.clone();
+
+    let window_clone = window.clone();
+    let handle_clone = handle.clone();
+
+    *closure.borrow_mut() = Some(Closure::wrap(Box::new(move |_time: f64| {
+        // The xr frames took over or winit polls the window again, so this loop ends here.
+        if !handle_clone.0.waiting.get() || handle_clone.session().is_some() {
+            handle_clone.0.window_loop.set(false);
+            return;
+        }
+
+        let _ = window_clone.request_animation_frame(
+            closure_clone
+                .borrow()
+                .as_ref()
+                .unwrap()
+                .as_ref()
+                .unchecked_ref(),
+        );
+
+        handle_clone.wake();
+    }) as Box<dyn FnMut(f64)>));
+
+    window
+        .request_animation_frame(closure.borrow().as_ref().unwrap().as_ref().unchecked_ref())
+        .map_err(|err| WebXrError::JsError(err))?;
+
+    handle.0.window_loop.set(true);
+
+    Ok(())
+}
+
+async fn get_supported_sessions() -> Result<WebXrSupport, WebXrError> {
+    let navigator = web_sys::window().ok_or(WebXrError::NoWindow)?.navigator();
+
+    // Browsers without webxr don't have navigator.xr at all.
+    if !js_sys::Reflect::has(&navigator, &"xr".into()).unwrap_or(false) {
+        return Err(WebXrError::NotSupported);
+    }
+
+    let xr = navigator.xr();
 
     let inline =
         wasm_bindgen_futures::JsFuture::from(xr.is_session_supported(XrSessionMode::Inline))
@@ -85,62 +398,300 @@ async fn get_supported_sessions() -> Result<SupportedSessions, WebXrError> {
             .as_bool()
             .ok_or(WebXrError::NotABool)?;
 
-    Ok(SupportedSessions { inline, vr, ar })
+    Ok(WebXrSupport { vr, ar, inline })
+}
+
+///
+/// The state to return to while no session is running, based on the current support.
+///
+fn idle_state(world: &World) -> WebXrSessionState {
+    let settings = world.resource::<WebXrSettings>();
+    let support = world
+        .get_resource::<WebXrSupport>()
+        .copied()
+        .unwrap_or_default();
+
+    if (support.vr & settings.vr_supported)
+        | (support.ar & settings.ar_supported)
+        | (support.inline & settings.inline_supported)
+    {
+        WebXrSessionState::Available
+    } else {
+        WebXrSessionState::Unsupported
+    }
+}
+
+fn update_support(world: &mut World, support: WebXrSupport, handle: &AppHandle) {
+    world.insert_resource(support);
+
+    // Sessions that are starting, running or ending update the state themselves.
+    if matches!(
+        session_state(world),
+        WebXrSessionState::Unsupported | WebXrSessionState::Available
+    ) {
+        let state = idle_state(world);
+        set_session_state(world, state);
+    }
+
+    let settings = world.resource::<WebXrSettings>().clone();
+
+    for (button_type, supported, enabled) in [
+        (XrButtonType::VR, support.vr, settings.vr_supported),
+        (XrButtonType::AR, support.ar, settings.ar_supported),
+    ] {
+        let exists = world
+            .non_send_resource::<XrButtons>()
+            .buttons
+            .iter()
+            .any(|button| button.button_type == button_type);
+
+        if supported & enabled & settings.dom_buttons {
+            if !exists {
+                info!(
+                    "Initialize {:?} button: {:?}",
+                    button_type,
+                    initialize_button(world, button_type, &settings, handle)
+                );
+            }
+        } else if exists {
+            info!("Remove {:?} button!", button_type);
+            remove_button(button_type, world);
+        }
+    }
 }
 
-#[derive(Clone, Copy)]
+///
+/// Headsets can be connected after the page loaded, so the support is checked again on every devicechange.
+///
+fn initialize_device_change(settings: WebXrSettings, handle: AppHandle) -> Result<(), WebXrError> {
+    let navigator = web_sys::window().ok_or(WebXrError::NoWindow)?.navigator();
+
+    if !js_sys::Reflect::has(&navigator, &"xr".into()).unwrap_or(false) {
+        return Err(WebXrError::NotSupported);
+    }
+
+    let closure = Closure::<dyn FnMut()>::new(move || {
+        let settings = settings.clone();
+        let handle = handle.clone();
+
+        spawn_local(async move {
+            let support = match get_supported_sessions().await {
+                Ok(support) => support,
+                Err(error) => {
+                    warn!("Failed to get supported sessions: {}", error);
+                    return;
+                }
+            };
+
+            info!("Device changed: {:?}", support);
+
+            let previous = Rc::new(Cell::new(WebXrSupport::default()));
+            let (previous_clone, handle_clone) = (previous.clone(), handle.clone());
+            handle
+                .run_and_wait(move |world| {
+                    previous_clone.set(
+                        world
+                            .get_resource::<WebXrSupport>()
+                            .copied()
+                            .unwrap_or_default(),
+                    );
+                    update_support(world, support, &handle_clone);
+                })
+                .await;
+
+            if !previous.get().inline
+                & support.inline
+                & settings.inline_supported
+                & handle.session().is_none()
+            {
+                initialize_session(XrMode::Inline, settings, handle).await;
+            }
+        });
+    });
+
+    navigator
+        .xr()
+        .set_ondevicechange(Some(closure.as_ref().unchecked_ref()));
+
+    closure.forget();
+
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum XrButtonType {
     VR,
     AR,
 }
 
+impl XrButtonType {
+    fn mode(&self) -> XrMode {
+        match self {
+            XrButtonType::VR => XrMode::VR,
+            XrButtonType::AR => XrMode::AR,
+        }
+    }
+}
+
+struct XrButton {
+    button_type: XrButtonType,
+    element: HtmlButtonElement,
+    /// Buttons that were created by us are removed again, buttons of the page are only hidden.
+    created: bool,
+}
+
+///
+/// The dom buttons and the mode of the session they can exit.
+/// The mode is shared with the click listeners, which can't reach the app.
+///
+#[derive(Default)]
+struct XrButtons {
+    buttons: Vec<XrButton>,
+    running: Rc<Cell<Option<XrMode>>>,
+}
+
+fn update_buttons(world: &mut World, state: WebXrSessionState) {
+    let labels = world.resource::<WebXrSettings>().button_labels.clone();
+    let Some(buttons) = world.get_non_send_resource::<XrButtons>() else {
+        return;
+    };
+
+    let running = match state {
+        // A hidden session is still running, so the buttons keep offering to exit it.
+        WebXrSessionState::Hidden => return,
+        WebXrSessionState::Running { mode } => Some(mode),
+        _ => None,
+    };
+    buttons.running.set(running);
+
+    for XrButton {
+        button_type,
+        element: button,
+        ..
+    } in buttons.buttons.iter()
+    {
+        let running = running == Some(button_type.mode());
+
+        button.set_disabled(matches!(
+            state,
+            WebXrSessionState::Requesting
+                | WebXrSessionState::Ending
+                | WebXrSessionState::Unsupported
+        ));
+        button.set_inner_text(match (button_type, running) {
+            (XrButtonType::VR, false) => &labels.enter_vr,
+            (XrButtonType::VR, true) => &labels.exit_vr,
+            (XrButtonType::AR, false) => &labels.enter_ar,
+            (XrButtonType::AR, true) => &labels.exit_ar,
+        });
+        // Exposes the state to the page's css.
+        let _ = button.set_attribute(
+            "data-state",
+            match state {
+                _ if running => "running",
+                WebXrSessionState::Requesting => "requesting",
+                WebXrSessionState::Ending => "ending",
+                WebXrSessionState::Unsupported => "unsupported",
+                _ => "available",
+            },
+        );
+    }
+}
+
+fn set_button_failed(world: &mut World, mode: XrMode) {
+    let failed = world
+        .resource::<WebXrSettings>()
+        .button_labels
+        .failed
+        .clone();
+    let Some(buttons) = world.get_non_send_resource::<XrButtons>() else {
+        return;
+    };
+
+    for button in buttons.buttons.iter() {
+        if button.button_type.mode() == mode {
+            button.element.set_inner_text(&failed);
+            let _ = button.element.set_attribute("data-state", "failed");
+        }
+    }
+}
+
+fn remove_button(button_type: XrButtonType, world: &mut World) {
+    let mut buttons = world.non_send_resource_mut::<XrButtons>();
+
+    buttons.buttons.retain(|button| {
+        if button.button_type != button_type {
+            return true;
+        }
+
+        button.element.set_onclick(None);
+        if button.created {
+            button.element.remove();
+        } else {
+            button.element.set_hidden(true);
+        }
+        false
+    });
+}
+
 fn initialize_button(
+    world: &mut World,
     button_type: XrButtonType,
-    settings: WebXrSettings,
-    app: Arc<Mutex<App>>,
+    settings: &WebXrSettings,
+    handle: &AppHandle,
 ) -> Result<(), WebXrError> {
     let document = web_sys::window()
         .ok_or(WebXrError::NoWindow)?
         .document()
         .ok_or(WebXrError::NoDocument)?;
 
-    let button: HtmlButtonElement = if let Ok(Some(element)) =
-        document.query_selector(match button_type {
+    let (button, created): (HtmlButtonElement, bool) = if let Ok(Some(element)) = document
+        .query_selector(match button_type {
             XrButtonType::VR => &settings.vr_button,
             XrButtonType::AR => &settings.ar_button,
         }) {
-        element
+        let button: HtmlButtonElement = element
             .dyn_into()
-            .map_err(|err| WebXrError::ElementNotButtonElement(err))?
+            .map_err(|err| WebXrError::ElementNotButtonElement(err))?;
+        button.set_hidden(false);
+        (button, false)
     } else {
         let button = document
             .create_element("button")
             .map_err(|err| WebXrError::JsError(err))?
             .dyn_into::<web_sys::HtmlButtonElement>()
             .map_err(|err| WebXrError::ElementNotButtonElement(err))?;
-        match button_type {
-            XrButtonType::VR => button.set_inner_text("Enter VR"),
-            XrButtonType::AR => button.set_inner_text("Enter AR"),
-        }
         document
             .body()
             .ok_or(WebXrError::NoBody)?
             .append_child(&button)
             .map_err(|err| WebXrError::JsError(err))?;
-        button
+        (button, true)
+    };
+
+    let running = {
+        let mut buttons = world.non_send_resource_mut::<XrButtons>();
+        buttons.buttons.push(XrButton {
+            button_type,
+            element: button.clone(),
+            created,
+        });
+        buttons.running.clone()
     };
+    let state = session_state(world);
+    update_buttons(world, state);
 
-    //button.set_attribute("disabled", "true").unwrap();
+    let settings = settings.clone();
+    let handle = handle.clone();
 
     let closure = Closure::<dyn FnMut()>::new(move || {
-        AsyncComputeTaskPool::get().spawn(initialize_session(
-            match button_type {
-                XrButtonType::VR => XrMode::VR,
-                XrButtonType::AR => XrMode::AR,
-            },
-            settings.clone(),
-            app.clone(),
-        ));
+        let mode = button_type.mode();
+
+        if running.get() == Some(mode) {
+            spawn_local(end_session(handle.clone()));
+        } else {
+            spawn_local(initialize_session(mode, settings.clone(), handle.clone()));
+        }
     });
 
     button.set_onclick(Some(closure.as_ref().unchecked_ref()));
@@ -150,60 +701,146 @@ fn initialize_button(
     Ok(())
 }
 
-async fn initialize_session(mode: XrMode, settings: WebXrSettings, app: Arc<Mutex<App>>) {
-    info!("Stopping previous session!");
+async fn initialize_inline_session(settings: WebXrSettings, handle: AppHandle) {
+    if let Ok(WebXrSupport { inline: true, .. }) = get_supported_sessions().await {
+        initialize_session(XrMode::Inline, settings, handle).await;
+    }
+}
+
+async fn end_session(handle: AppHandle) {
+    if let Some(session) = handle.session() {
+        handle.run(|world| set_session_state(world, WebXrSessionState::Ending));
 
-    if let Some(frame) = app
-        .lock()
-        .unwrap()
-        .world
-        .remove_non_send_resource::<WebXrFrame>()
-    {
         info!(
             "Session ended: {:?}",
-            wasm_bindgen_futures::JsFuture::from(frame.webxr_frame.session().end()).await
+            wasm_bindgen_futures::JsFuture::from(session.end()).await
         );
     }
+}
+
+async fn initialize_session(mode: XrMode, settings: WebXrSettings, handle: AppHandle) {
+    info!("Stopping previous session!");
+
+    end_session(handle.clone()).await;
 
     info!("Requesting session!");
 
-    let session = request_session(mode).await;
+    // Winit has to wait for the xr frames before the session starts, see update_winit_settings.
+    handle
+        .run_and_wait(|world| set_session_state(world, WebXrSessionState::Requesting))
+        .await;
+
+    let session = request_session(mode, settings.features(mode), &settings.origin).await;
 
     info!("Session requested: {:?}", session);
 
-    let session = session.unwrap_throw();
+    let result = match session {
+        Ok(session) => {
+            let result =
+                initialize_session_rendering(&session, mode, &settings, handle.clone()).await;
+            if result.is_err() {
+                // The session is useless without rendering, so leave it right away.
+                info!(
+                    "Session ended: {:?}",
+                    wasm_bindgen_futures::JsFuture::from(session.end()).await
+                );
+            }
+            result
+        }
+        Err(err) => Err(err),
+    };
 
+    if let Err(error) = result {
+        error!("Failed to initialize session: {}", error);
+
+        handle.run(move |world| {
+            world.send_event(WebXrSessionFailed::new(mode, &error));
+            let state = idle_state(world);
+            set_session_state(world, state);
+            set_button_failed(world, mode);
+        });
+    }
+}
+
+async fn initialize_session_rendering(
+    session: &XrSession,
+    mode: XrMode,
+    settings: &WebXrSettings,
+    handle: AppHandle,
+) -> Result<(), WebXrError> {
     let canvas = initialize_canvas(&settings.canvas);
 
     info!("Canvas initialized: {:?}", canvas);
 
-    let canvas = canvas.unwrap_throw();
+    let canvas = canvas?;
 
-    let reference_space = initialize_reference_space(&session, &mode, &settings.origin).await;
+    let reference_space = initialize_reference_space(session, &mode, &settings.origin).await;
 
     info!("Reference space initialized: {:?}", reference_space);
 
-    let reference_space = reference_space.unwrap_throw();
+    let (reference_space, reference_space_type) = reference_space?;
 
-    info!(
-        "Render context initialized: {:?}",
-        initialize_render_context(&session, &canvas).await
-    );
+    let render_context = initialize_render_context(session, &canvas, &settings.layer).await;
 
-    info!(
-        "Frame initialized: {:?}",
-        request_first_web_xr_frame(&session, reference_space, app, mode, settings.origin)
+    info!("Render context initialized: {:?}", render_context);
+
+    let framebuffer_format = render_context?;
+
+    handle.run(move |world| {
+        world.insert_resource(WebXrReferenceSpaceType(reference_space_type));
+        world.insert_resource(WebXrFramebufferFormat(framebuffer_format));
+    });
+
+    let frame = request_first_web_xr_frame(
+        session,
+        reference_space,
+        handle,
+        mode,
+        settings,
+        get_enabled_features(session, settings.features(mode)),
     );
+
+    info!("Frame initialized: {:?}", frame);
+
+    frame
 }
 
-async fn request_session(mode: XrMode) -> Result<XrSession, WebXrError> {
+async fn request_session(
+    mode: XrMode,
+    features: &WebXrSessionFeatures,
+    xr_origin: &XrOrigin,
+) -> Result<XrSession, WebXrError> {
     let xr = web_sys::window()
         .ok_or(WebXrError::NoWindow)?
         .navigator()
         .xr();
 
-    //js_sys::Array::of1(&"local-floor".into());
-    let features = js_sys::Array::of1(&"hand-tracking".into());
+    let required_features = features
+        .required
+        .iter()
+        .map(|feature| JsValue::from_str(feature))
+        .collect::<js_sys::Array>();
+    let optional_features = features
+        .optional
+        .iter()
+        .map(|feature| JsValue::from_str(feature))
+        // The reference spaces of the origin's fallback chain are only available if their features are enabled.
+        .chain(
+            reference_space_types(&mode, xr_origin)
+                .iter()
+                .filter_map(reference_space_feature)
+                .filter(|feature| {
+                    !features.required.iter().any(|required| required == feature)
+                        && !features.optional.iter().any(|optional| optional == feature)
+                })
+                .map(JsValue::from_str),
+        )
+        .collect::<js_sys::Array>();
+
+    let mut session_init = XrSessionInit::new();
+    session_init
+        .required_features(&required_features)
+        .optional_features(&optional_features);
 
     let session = wasm_bindgen_futures::JsFuture::from(xr.request_session_with_options(
         match mode {
@@ -211,7 +848,7 @@ async fn request_session(mode: XrMode) -> Result<XrSession, WebXrError> {
             XrMode::AR => XrSessionMode::ImmersiveAr,
             XrMode::Inline => XrSessionMode::Inline,
         },
-        &XrSessionInit::new().optional_features(&features),
+        &session_init,
     ))
     .await
     .map(|session| session.into())
@@ -220,6 +857,42 @@ async fn request_session(mode: XrMode) -> Result<XrSession, WebXrError> {
     session
 }
 
+///
+/// Not available in web_sys yet. Sessions without it are treated as opaque, like every immersive-vr session on a headset.
+///
+fn get_environment_blend_mode(session: &XrSession) -> WebXrEnvironmentBlendMode {
+    let blend_mode = js_sys::Reflect::get(session, &"environmentBlendMode".into())
+        .ok()
+        .and_then(|blend_mode| blend_mode.as_string());
+
+    info!("Environment blend mode: {:?}", blend_mode);
+
+    match blend_mode.as_deref() {
+        Some("additive") => WebXrEnvironmentBlendMode::Additive,
+        Some("alpha-blend") => WebXrEnvironmentBlendMode::AlphaBlend,
+        _ => WebXrEnvironmentBlendMode::Opaque,
+    }
+}
+
+fn get_enabled_features(
+    session: &XrSession,
+    features: &WebXrSessionFeatures,
+) -> WebXrEnabledFeatures {
+    match js_sys::Reflect::get(session, &"enabledFeatures".into()) {
+        Ok(enabled_features) if js_sys::Array::is_array(&enabled_features) => WebXrEnabledFeatures(
+            js_sys::Array::from(&enabled_features)
+                .iter()
+                .filter_map(|feature| feature.as_string())
+                .collect(),
+        ),
+        _ => {
+            // Browsers without session.enabledFeatures only guarantee the required features.
+            warn!("Failed to get enabled features of session!");
+            WebXrEnabledFeatures(features.required.clone())
+        }
+    }
+}
+
 pub fn initialize_canvas(canvas: &str) -> Result<web_sys::HtmlCanvasElement, WebXrError> {
     let window = web_sys::window().ok_or(WebXrError::NoWindow)?;
     let document = window.document().ok_or(WebXrError::NoDocument)?;
@@ -237,7 +910,9 @@ pub fn initialize_canvas(canvas: &str) -> Result<web_sys::HtmlCanvasElement, Web
             .dyn_into::<HtmlCanvasElement>()
             .map_err(|err| WebXrError::ElementNotCanvasElement(err))?;
         canvas_element.set_id(canvas);
-        canvas_element.set_attribute(canvas, canvas).unwrap();
+        canvas_element
+            .set_attribute(canvas, canvas)
+            .map_err(|err| WebXrError::JsError(err))?;
         document
             .body()
             .ok_or(WebXrError::NoBody)?
@@ -250,7 +925,8 @@ pub fn initialize_canvas(canvas: &str) -> Result<web_sys::HtmlCanvasElement, Web
 async fn initialize_render_context(
     session: &XrSession,
     canvas: &HtmlCanvasElement,
-) -> Result<(), WebXrError> {
+    layer_settings: &WebXrLayerSettings,
+) -> Result<TextureFormat, WebXrError> {
     let context = canvas
         .get_context("webgl2")
         .map_err(|err| WebXrError::JsError(err))?
@@ -267,9 +943,23 @@ async fn initialize_render_context(
     info!("Promise of make xr compatible: {:?}", promise);
     info!("Rendering context after xr compatible: {:?}", context);
 
-    let web_gl_layer =
-        web_sys::XrWebGlLayer::new_with_web_gl2_rendering_context(&session, &context)
-            .map_err(|err| WebXrError::JsError(err))?;
+    let mut layer_init = web_sys::XrWebGlLayerInit::new();
+    layer_init
+        .framebuffer_scale_factor(layer_settings.framebuffer_scale_factor)
+        .antialias(layer_settings.antialias)
+        .alpha(layer_settings.alpha)
+        .depth(layer_settings.depth)
+        .stencil(layer_settings.stencil)
+        .ignore_depth_values(layer_settings.ignore_depth_values);
+
+    info!("XrWebGlLayerInit: {:?}", layer_init);
+
+    let web_gl_layer = web_sys::XrWebGlLayer::new_with_web_gl2_rendering_context_and_layer_init(
+        &session,
+        &context,
+        &layer_init,
+    )
+    .map_err(|err| WebXrError::JsError(err))?;
 
     info!("XrWeGlLayer: {:?}", web_gl_layer);
 
@@ -281,8 +971,8 @@ async fn initialize_render_context(
     );
 
     render_state_init.base_layer(Some(&web_gl_layer));
-    render_state_init.depth_near(0.001);
-    // render_state_init.depth_far(0.01);
+    render_state_init.depth_near(layer_settings.depth_near);
+    render_state_init.depth_far(layer_settings.depth_far);
 
     info!(
         "Render state init after base layer: {:?}",
@@ -296,40 +986,120 @@ async fn initialize_render_context(
         session.render_state().base_layer()
     );
 
-    Ok(())
+    let framebuffer_encoding = layer_settings
+        .framebuffer_encoding
+        .unwrap_or_else(|| assumed_framebuffer_encoding(&web_gl_layer));
+
+    info!("Framebuffer encoding: {:?}", framebuffer_encoding);
+
+    Ok(framebuffer_encoding.texture_format())
+}
+
+///
+/// WebXR makes getFramebufferAttachmentParameter on the opaque xr framebuffer an INVALID_OPERATION, so its color encoding
+/// can't be queried. Browsers create it like the default framebuffer of a canvas, which is linear, and the compositor
+/// treats its values as srgb. So linear is assumed and the cameras convert to srgb themselves.
+///
+fn assumed_framebuffer_encoding(web_gl_layer: &web_sys::XrWebGlLayer) -> WebXrFramebufferEncoding {
+    match js_sys::Reflect::get(web_gl_layer, &"framebuffer".into()) {
+        Ok(framebuffer) if !framebuffer.is_null() => WebXrFramebufferEncoding::Linear,
+        // Inline sessions render into the canvas, which bevy already handles.
+        _ => WebXrFramebufferEncoding::Srgb,
+    }
+}
+
+///
+/// The reference spaces tried for an origin, in order of preference.
+///
+fn reference_space_types(
+    xr_mode: &XrMode,
+    xr_origin: &XrOrigin,
+) -> &'static [XrReferenceSpaceType] {
+    match (xr_mode, xr_origin) {
+        (XrMode::VR | XrMode::AR, XrOrigin::View) => &[XrReferenceSpaceType::Local],
+        (XrMode::VR | XrMode::AR, XrOrigin::Seat) => &[XrReferenceSpaceType::Local],
+        (XrMode::VR | XrMode::AR, XrOrigin::Room) => &[
+            XrReferenceSpaceType::BoundedFloor,
+            XrReferenceSpaceType::LocalFloor,
+            XrReferenceSpaceType::Local,
+        ],
+        (XrMode::VR | XrMode::AR, XrOrigin::Other) => &[
+            XrReferenceSpaceType::Unbounded,
+            XrReferenceSpaceType::LocalFloor,
+        ],
+        // Local is only available where the browser tracks the device orientation, e.g. on phones.
+        (XrMode::Inline, _) => &[XrReferenceSpaceType::Local, XrReferenceSpaceType::Viewer],
+    }
+}
+
+///
+/// The session feature a reference space has to be requested with. Local is enabled by default for immersive sessions.
+///
+fn reference_space_feature(space_type: &XrReferenceSpaceType) -> Option<&'static str> {
+    match space_type {
+        XrReferenceSpaceType::Local => Some("local"),
+        XrReferenceSpaceType::LocalFloor => Some("local-floor"),
+        XrReferenceSpaceType::BoundedFloor => Some("bounded-floor"),
+        XrReferenceSpaceType::Unbounded => Some("unbounded"),
+        _ => None,
+    }
 }
 
 async fn initialize_reference_space(
     session: &XrSession,
     xr_mode: &XrMode,
     xr_origin: &XrOrigin,
-) -> Result<XrReferenceSpace, WebXrError> {
-    let space_type = match (xr_mode, xr_origin) {
-        (XrMode::VR, XrOrigin::View) => XrReferenceSpaceType::Local,
-        (XrMode::VR, XrOrigin::Seat) => XrReferenceSpaceType::Local, // TODO: Choose on UserAgent
-        (XrMode::VR, XrOrigin::Room) => XrReferenceSpaceType::Local, // TODO: ::FloorBounded?
-        (XrMode::VR, XrOrigin::Other) => XrReferenceSpaceType::Unbounded,
-        (XrMode::AR, XrOrigin::View) => XrReferenceSpaceType::Local,
-        (XrMode::AR, XrOrigin::Seat) => XrReferenceSpaceType::Local, // TODO: Choose on UserAgent
-        (XrMode::AR, XrOrigin::Room) => XrReferenceSpaceType::Local, // TODO: ::FloorBounded?
-        (XrMode::AR, XrOrigin::Other) => XrReferenceSpaceType::Unbounded,
-        (XrMode::Inline, _) => XrReferenceSpaceType::Viewer,
-    };
+) -> Result<(XrReferenceSpace, XrReferenceSpaceType), WebXrError> {
+    let mut result = Err(WebXrError::NotSupported);
 
-    let reference_space = JsFuture::from(session.request_reference_space(space_type))
-        .await
-        .map_err(|err| WebXrError::JsError(err))?
-        .into();
+    for space_type in reference_space_types(xr_mode, xr_origin) {
+        result = JsFuture::from(session.request_reference_space(*space_type))
+            .await
+            .map(|reference_space| (reference_space.into(), *space_type))
+            .map_err(|err| WebXrError::JsError(err));
+
+        if result.is_ok() {
+            break;
+        }
 
-    Ok(reference_space)
+        info!(
+            "Reference space {:?} not available: {:?}",
+            space_type, result
+        );
+    }
+
+    result
+}
+
+fn frame_timing(
+    time: f64,
+    frame: &web_sys::XrFrame,
+    previous: Option<&WebXrFrameTiming>,
+) -> WebXrFrameTiming {
+    // The first frame of a session has nothing to measure against.
+    let interval = previous
+        .map(|previous| Duration::from_secs_f64(((time - previous.time) / 1000.0).max(0.0)))
+        .unwrap_or_default();
+
+    // Looked up instead of calling XrFrame::predicted_display_time, which would read undefined as NaN on browsers without it.
+    let predicted_display_time = js_sys::Reflect::get(frame, &"predictedDisplayTime".into())
+        .ok()
+        .and_then(|value| value.as_f64());
+
+    WebXrFrameTiming {
+        time,
+        predicted_display_time,
+        interval,
+    }
 }
 
 fn request_first_web_xr_frame(
     session: &XrSession,
     reference_space: XrReferenceSpace,
-    app: Arc<Mutex<App>>,
+    handle: AppHandle,
     mode: XrMode,
-    origin: XrOrigin,
+    settings: &WebXrSettings,
+    enabled_features: WebXrEnabledFeatures,
 ) -> Result<(), WebXrError> {
     info!("Starting webxr rendering!");
 
@@ -339,13 +1109,17 @@ fn request_first_web_xr_frame(
         Rc::new(RefCell::new(None));
     let closure_clone = closure.clone();
 
-    let app_clone = app.clone();
+    let handle_clone = handle.clone();
 
     *closure.borrow_mut() = Some(Closure::wrap(Box::new(
         move |time: f64, frame: web_sys::XrFrame| {
             //info!("Update xr frame!");
 
-            // TODO: Check if this works or if it has to happen after app.update()
+            // Another session or the window took over, so this loop ends here.
+            if handle_clone.session() != Some(frame.session()) {
+                return;
+            }
+
             let _frame_index = frame.session().request_animation_frame(
                 closure_clone
                     .borrow()
@@ -355,41 +1129,156 @@ fn request_first_web_xr_frame(
                     .unchecked_ref(),
             );
 
-            let mut app = app_clone.lock().unwrap();
-
-            app.world.insert_non_send_resource(WebXrFrame {
+            *handle_clone.0.frame.borrow_mut() = Some(WebXrFrame {
                 time: time,
                 webxr_frame: frame,
                 webxr_reference_space: reference_space.clone(),
             });
 
-            app.update();
+            // Winit waits for events during sessions, so this updates the app right away.
+            handle_clone.wake();
+
+            // A busy winit updates later without this frame, as the frame is only valid within this callback.
+            handle_clone.0.frame.borrow_mut().take();
 
             //print_frame_index(frame_index);
         },
     )
         as Box<dyn FnMut(f64, web_sys::XrFrame)>));
 
-    let frame_index = session
-        .request_animation_frame(closure.borrow().as_ref().unwrap().as_ref().unchecked_ref());
+    initialize_session_events(session, handle.clone(), mode, settings.clone());
 
-    print_frame_index(frame_index);
+    let session = session.clone();
+    let origin = settings.origin;
+    let environment_blend_mode = get_environment_blend_mode(&session);
+    let frame_rate = WebXrFrameRate {
+        supported_frame_rates: session.supported_frame_rates().unwrap_or_default(),
+        frame_rate: session.frame_rate(),
+        target_frame_rate: None,
+    };
+    let handle_clone = handle.clone();
 
-    {
-        let mut app = app.lock().unwrap();
+    // The first xr frame is only requested once winit waits for it, see update_winit_settings.
+    handle.run(move |world| {
+        *handle_clone.0.animation_loop.borrow_mut() = AnimationLoop::Xr(session.clone());
 
-        app.world
-            .send_event(WebXrSessionInitialized { mode, origin });
+        set_session_state(world, WebXrSessionState::Running { mode });
 
-        if app.plugins_state() == PluginsState::Ready {
-            app.finish();
-            app.cleanup();
-        }
-    }
+        world.insert_resource(enabled_features);
+
+        world.insert_resource(environment_blend_mode);
+
+        world.insert_resource(frame_rate);
+
+        world.send_event(WebXrSessionInitialized { mode, origin });
+
+        let frame_index = session
+            .request_animation_frame(closure.borrow().as_ref().unwrap().as_ref().unchecked_ref());
+
+        print_frame_index(frame_index);
+    });
 
     Ok(())
 }
 
+fn initialize_session_events(
+    session: &XrSession,
+    handle: AppHandle,
+    mode: XrMode,
+    settings: WebXrSettings,
+) {
+    let handle_clone = handle.clone();
+    let end_closure = Closure::<dyn FnMut(XrSessionEvent)>::new(move |event: XrSessionEvent| {
+        info!("Session ended!");
+
+        let handle = handle_clone.clone();
+        let settings = settings.clone();
+
+        handle_clone.run(move |world| {
+            world.send_event(WebXrSessionEnded { mode });
+
+            // Hand the app back to the window unless another session already took over.
+            if handle.session() != Some(event.session()) {
+                return;
+            }
+
+            *handle.0.animation_loop.borrow_mut() = AnimationLoop::Window;
+
+            world.remove_non_send_resource::<WebXrFrame>();
+            world.remove_resource::<WebXrEnabledFeatures>();
+            world.remove_resource::<WebXrFrameTiming>();
+            world.remove_resource::<WebXrFrameRate>();
+            world.remove_resource::<WebXrFramebufferFormat>();
+            world.remove_resource::<WebXrEnvironmentBlendMode>();
+            world.remove_resource::<WebXrReferenceSpaceType>();
+            if settings.xr_frame_time {
+                world.insert_resource(TimeUpdateStrategy::Automatic);
+            }
+            let state = idle_state(world);
+            set_session_state(world, state);
+
+            // Leaving an immersive session returns to the inline session where possible.
+            if mode != XrMode::Inline && settings.inline_supported {
+                spawn_local(initialize_inline_session(settings, handle.clone()));
+            }
+        });
+    });
+
+    session.set_onend(Some(end_closure.as_ref().unchecked_ref()));
+
+    end_closure.forget();
+
+    let handle_clone = handle.clone();
+    let visibility_closure =
+        Closure::<dyn FnMut(XrSessionEvent)>::new(move |event: XrSessionEvent| {
+            let visibility = match event.session().visibility_state() {
+                XrVisibilityState::Visible => WebXrVisibilityChanged::Visible,
+                XrVisibilityState::VisibleBlurred => WebXrVisibilityChanged::VisibleBlurred,
+                XrVisibilityState::Hidden => WebXrVisibilityChanged::Hidden,
+                _ => return,
+            };
+
+            info!("Session visibility changed: {:?}", visibility);
+
+            handle_clone.run(move |world| {
+                world.send_event(visibility);
+
+                set_session_state(
+                    world,
+                    match visibility {
+                        WebXrVisibilityChanged::Hidden => WebXrSessionState::Hidden,
+                        _ => WebXrSessionState::Running { mode },
+                    },
+                );
+            });
+        });
+
+    session.set_onvisibilitychange(Some(visibility_closure.as_ref().unchecked_ref()));
+
+    visibility_closure.forget();
+
+    let frame_rate_closure =
+        Closure::<dyn FnMut(XrSessionEvent)>::new(move |event: XrSessionEvent| {
+            let frame_rate = event.session().frame_rate();
+
+            info!("Frame rate changed: {:?}", frame_rate);
+
+            handle.run(move |world| {
+                if let Some(mut resource) = world.get_resource_mut::<WebXrFrameRate>() {
+                    resource.frame_rate = frame_rate;
+                }
+
+                if let Some(frame_rate) = frame_rate {
+                    world.send_event(WebXrFrameRateChanged { frame_rate });
+                }
+            });
+        });
+
+    session.set_onframeratechange(Some(frame_rate_closure.as_ref().unchecked_ref()));
+
+    frame_rate_closure.forget();
+}
+
 fn print_frame_index(frame_index: u32) {
     let mut string = "Xr Frame #".to_string();
     string.push_str(&frame_index.to_string());
@@ -1,8 +1,11 @@
 use bevy::{
+    app::MainScheduleOrder,
+    core_pipeline::clear_color::ClearColorConfig,
     input::InputSystem,
     prelude::*,
     render::{
         camera::{camera_system, CameraProjectionPlugin},
+        render_resource::TextureFormat,
         view::{update_frusta, VisibilitySystems},
     },
     transform::TransformSystem,
@@ -11,17 +14,24 @@ use bevy_xr::{
     pointer::{LeftHanded, RightHanded},
     space::XrOrigin,
 };
-use projection::WebXrProjection;
+use std::{cmp::Ordering, time::Duration};
+use web_sys::XrReferenceSpaceType;
 
 pub mod error;
 pub mod events;
+pub mod state;
 
+mod clear_color;
 mod dom_point;
 mod init;
 mod projection;
+mod srgb_conversion;
 mod tracked;
 
-#[derive(Clone, Copy)]
+pub use projection::WebXrProjection;
+pub use tracked::spectator::XrSpectator;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum XrMode {
     VR,
     AR,
@@ -33,10 +43,19 @@ pub struct WebXrSettings {
     pub vr_supported: bool,
     pub ar_supported: bool,
     pub inline_supported: bool,
+    /// Adds "Enter VR" and "Enter AR" buttons to the page. Without them sessions are entered with events::WebXrCommand.
+    pub dom_buttons: bool,
     pub vr_button: String,
     pub ar_button: String,
+    pub button_labels: WebXrButtonLabels,
     pub canvas: String,
     pub origin: XrOrigin,
+    pub vr_features: WebXrSessionFeatures,
+    pub ar_features: WebXrSessionFeatures,
+    pub inline_features: WebXrSessionFeatures,
+    pub layer: WebXrLayerSettings,
+    /// Advances Time<Real> and Time<Virtual> by the interval between xr frames while a session is running.
+    pub xr_frame_time: bool,
 }
 
 impl Default for WebXrSettings {
@@ -44,15 +63,145 @@ impl Default for WebXrSettings {
         WebXrSettings {
             vr_supported: true,
             ar_supported: true,
-            inline_supported: false, // Not implemented yet.
+            inline_supported: false,
+            dom_buttons: true,
             vr_button: "vr_button".to_string(),
             ar_button: "ar_button".to_string(),
+            button_labels: WebXrButtonLabels::default(),
             canvas: "canvas[alt=\"App\"]".to_string(),
             origin: XrOrigin::Room,
+            vr_features: WebXrSessionFeatures::optional(&["hand-tracking", "secondary-views"]),
+            ar_features: WebXrSessionFeatures::optional(&["hand-tracking", "secondary-views"]),
+            inline_features: WebXrSessionFeatures::optional(&["local"]),
+            layer: WebXrLayerSettings::default(),
+            xr_frame_time: false,
+        }
+    }
+}
+
+impl WebXrSettings {
+    pub fn features(&self, mode: XrMode) -> &WebXrSessionFeatures {
+        match mode {
+            XrMode::VR => &self.vr_features,
+            XrMode::AR => &self.ar_features,
+            XrMode::Inline => &self.inline_features,
+        }
+    }
+}
+
+///
+/// The features a session is requested with, e.g. "local-floor", "hit-test", "anchors", "dom-overlay" or "depth-sensing".
+/// The session request fails if a required feature is not available. Optional features are granted if possible.
+///
+#[derive(Clone, Default, Debug)]
+pub struct WebXrSessionFeatures {
+    pub required: Vec<String>,
+    pub optional: Vec<String>,
+}
+
+impl WebXrSessionFeatures {
+    pub fn required(features: &[&str]) -> Self {
+        Self {
+            required: features.iter().map(|feature| feature.to_string()).collect(),
+            optional: Vec::new(),
+        }
+    }
+
+    pub fn optional(features: &[&str]) -> Self {
+        Self {
+            required: Vec::new(),
+            optional: features.iter().map(|feature| feature.to_string()).collect(),
         }
     }
 }
 
+///
+/// Settings of the XRWebGLLayer and render state, applied when a session is started.
+/// The browser may clamp the values, e.g. the framebuffer scale factor.
+///
+#[derive(Clone, Debug)]
+pub struct WebXrLayerSettings {
+    /// Scales the resolution of the xr framebuffer relative to the device's default resolution.
+    pub framebuffer_scale_factor: f64,
+    pub antialias: bool,
+    pub alpha: bool,
+    pub depth: bool,
+    pub stencil: bool,
+    /// Tells the compositor to ignore the depth buffer, e.g. for reprojection.
+    /// The xr cameras render depth into bevy's own depth textures, so the layer's depth buffer only holds its clear value,
+    /// see notes.md.
+    pub ignore_depth_values: bool,
+    /// The color encoding of the xr framebuffer. Browsers don't expose it, so immersive sessions assume linear if None.
+    /// Linear framebuffers get a conversion to srgb at the end of the camera's post processing.
+    pub framebuffer_encoding: Option<WebXrFramebufferEncoding>,
+    /// Has to be closer than depth_far, as the projections are converted to bevy's infinite reversed depth.
+    pub depth_near: f64,
+    pub depth_far: f64,
+}
+
+impl Default for WebXrLayerSettings {
+    fn default() -> WebXrLayerSettings {
+        WebXrLayerSettings {
+            framebuffer_scale_factor: 1.0,
+            antialias: true,
+            alpha: true,
+            depth: true,
+            stencil: false,
+            ignore_depth_values: false,
+            framebuffer_encoding: None,
+            depth_near: 0.001,
+            depth_far: 1000.0,
+        }
+    }
+}
+
+///
+/// The session modes the browser currently supports. Updated when a headset is connected or disconnected.
+///
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Resource)]
+pub struct WebXrSupport {
+    pub vr: bool,
+    pub ar: bool,
+    pub inline: bool,
+}
+
+///
+/// The texts of the dom buttons, e.g. to localize them.
+///
+#[derive(Clone, Debug)]
+pub struct WebXrButtonLabels {
+    pub enter_vr: String,
+    pub exit_vr: String,
+    pub enter_ar: String,
+    pub exit_ar: String,
+    /// Shown after a session failed to start until the button is clicked again.
+    pub failed: String,
+}
+
+impl Default for WebXrButtonLabels {
+    fn default() -> WebXrButtonLabels {
+        WebXrButtonLabels {
+            enter_vr: "Enter VR".to_string(),
+            exit_vr: "Exit VR".to_string(),
+            enter_ar: "Enter AR".to_string(),
+            exit_ar: "Exit AR".to_string(),
+            failed: "XR failed".to_string(),
+        }
+    }
+}
+
+///
+/// The features the browser granted to the running session. Only present while a session is running.
+///
+#[derive(Clone, Default, Debug, Resource)]
+pub struct WebXrEnabledFeatures(pub Vec<String>);
+
+impl WebXrEnabledFeatures {
+    pub fn contains(&self, feature: &str) -> bool {
+        self.0.iter().any(|enabled| enabled == feature)
+    }
+}
+
 #[derive(Default)]
 pub struct WebXrPlugin {
     pub settings: WebXrSettings,
@@ -62,6 +211,7 @@ impl Plugin for WebXrPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(CameraProjectionPlugin::<WebXrProjection>::default());
         app.add_plugins(bevy_xr::render::FlipViewPlugin);
+        app.add_plugins(srgb_conversion::SrgbConversionPlugin);
 
         app.add_plugins(bevy_xr::controller_input::XrControllerInputPlugin);
 
@@ -70,21 +220,60 @@ impl Plugin for WebXrPlugin {
 
         events::add_events(app);
 
+        app.add_systems(First, init::run_tasks.before(bevy::time::TimeSystem));
+        app.add_systems(Last, init::handle_commands);
+
+        app.init_schedule(init::WebXrRunnerUpdate);
+        app.world
+            .resource_mut::<MainScheduleOrder>()
+            .insert_after(Last, init::WebXrRunnerUpdate);
+        app.add_systems(init::WebXrRunnerUpdate, init::update_winit_settings);
+
+        app.add_state::<state::WebXrSessionState>();
+
+        app.init_non_send_resource::<tracked::camera::XrFramebufferCache>();
+
         app.add_systems(
             PreUpdate,
             (
                 set_xr_mode,
                 tracked::space::initialize_xr_space,
+                tracked::camera::release_framebuffer_texture_view,
                 tracked::camera::update_xr_cameras,
-                tracked::controllers::update_xr_controllers.before(InputSystem),
-                tracked::hands::update_xr_hands::<LeftHanded>.in_set(InputSystem),
-                tracked::hands::update_xr_hands::<RightHanded>.in_set(InputSystem),
+                // Chained systems don't apply commands in between, so the cameras spawned this frame are applied here.
+                apply_deferred,
+                srgb_conversion::update_srgb_conversion,
+                clear_color::update_xr_clear_color,
+                tracked::controllers::update_xr_controllers
+                    .before(InputSystem)
+                    .run_if(not(between_xr_frames)),
+                tracked::hands::update_xr_hands::<LeftHanded>
+                    .in_set(InputSystem)
+                    .run_if(not(between_xr_frames)),
+                tracked::hands::update_xr_hands::<RightHanded>
+                    .in_set(InputSystem)
+                    .run_if(not(between_xr_frames)),
                 bevy_xr::systems::substitute_local_palm::<LeftHanded>.in_set(InputSystem),
                 bevy_xr::systems::substitute_local_palm::<RightHanded>.in_set(InputSystem),
             )
                 .chain(),
         );
 
+        app.init_resource::<WebXrFoveation>();
+        app.init_resource::<WebXrClearColor>();
+        app.init_resource::<WebXrViewportScale>();
+        app.init_resource::<WebXrSpectator>();
+        app.init_resource::<WebXrSecondaryViews>();
+
+        app.add_systems(
+            Last,
+            (
+                request_target_frame_rate,
+                (update_automatic_foveation, apply_foveation).chain(),
+                update_automatic_viewport_scale,
+            ),
+        );
+
         app.add_systems(
             PostUpdate,
             update_frusta::<projection::WebXrProjection>
@@ -93,6 +282,14 @@ impl Plugin for WebXrPlugin {
                 .after(TransformSystem::TransformPropagate)
                 .ambiguous_with(update_frusta::<Projection>),
         );
+
+        app.add_systems(
+            PostUpdate,
+            tracked::spectator::update_xr_spectator
+                .after(TransformSystem::TransformPropagate)
+                .before(VisibilitySystems::UpdatePerspectiveFrusta)
+                .before(VisibilitySystems::UpdateProjectionFrusta),
+        );
     }
 }
 
@@ -102,7 +299,202 @@ pub struct WebXrFrame {
     pub webxr_reference_space: web_sys::XrReferenceSpace,
 }
 
-fn set_xr_mode(mut event: EventReader<events::WebXrSessionInitialized>, mut commands: Commands) {
+///
+/// Renders the xr views into a part of their full viewport, from 1.0 for the full viewport down to min_scale.
+/// Cheaper than lowering the framebuffer scale factor, as it can change every frame. Browsers may ignore it.
+///
+#[derive(Clone, Debug, Resource)]
+pub struct WebXrViewportScale {
+    pub scale: f32,
+    /// Lowers the scale while frames miss the session's frame rate and raises it again once they keep up.
+    /// Without a reported frame rate, the shortest frame interval of the session is taken as the frame rate.
+    /// The browser's recommended viewport scale caps the scale, if it reports one.
+    pub automatic: bool,
+    pub min_scale: f32,
+}
+
+impl Default for WebXrViewportScale {
+    fn default() -> WebXrViewportScale {
+        WebXrViewportScale {
+            scale: 1.0,
+            automatic: false,
+            min_scale: 0.5,
+        }
+    }
+}
+
+///
+/// Mirrors immersive sessions into the page's window, e.g. for demos or streaming.
+/// The spectator camera carries XrSpectator and follows the XrHead or the target entity.
+///
+#[derive(Clone, Debug, Resource)]
+pub struct WebXrSpectator {
+    pub enabled: bool,
+    /// Follows this entity instead of the XrHead, e.g. a third person camera.
+    pub target: Option<Entity>,
+    /// The time in seconds it takes to catch up with most of a movement. 0.0 follows without smoothing.
+    pub smoothing: f32,
+    /// The vertical field of view in radians.
+    pub fov: f32,
+}
+
+impl Default for WebXrSpectator {
+    fn default() -> WebXrSpectator {
+        WebXrSpectator {
+            enabled: false,
+            target: None,
+            smoothing: 0.2,
+            fov: std::f32::consts::FRAC_PI_3,
+        }
+    }
+}
+
+///
+/// Rendering of secondary views, like the first person observer view used for mixed reality capture.
+/// They are only provided if the "secondary-views" feature is enabled.
+///
+#[derive(Clone, Debug, Resource)]
+pub struct WebXrSecondaryViews {
+    pub enabled: bool,
+    /// Renders into a part of the view's viewport to save performance, see WebXrViewportScale.
+    pub viewport_scale: f32,
+}
+
+impl Default for WebXrSecondaryViews {
+    fn default() -> WebXrSecondaryViews {
+        WebXrSecondaryViews {
+            enabled: true,
+            viewport_scale: 1.0,
+        }
+    }
+}
+
+///
+/// Marks the camera of a secondary view, e.g. to give it cheaper settings than the eyes.
+///
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct XrSecondaryView(pub u8);
+
+///
+/// The tracked viewer, placed between the eyes. Spawned as a child of the active XrOrigin with the xr cameras as its children.
+///
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct XrHead;
+
+///
+/// How every xr view is cleared. See-through displays always clear transparent.
+///
+#[derive(Clone, Debug, Resource)]
+pub struct WebXrClearColor(pub ClearColorConfig);
+
+impl Default for WebXrClearColor {
+    fn default() -> WebXrClearColor {
+        WebXrClearColor(ClearColorConfig::Custom(Color::NONE))
+    }
+}
+
+///
+/// How the running session's views are combined with the real environment.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Resource)]
+pub enum WebXrEnvironmentBlendMode {
+    Opaque,
+    Additive,
+    AlphaBlend,
+}
+
+///
+/// The reference space type the running session got for the XrOrigin. Falls back along the origin's preferred spaces,
+/// e.g. Room tries bounded-floor, local-floor and then local. With local the origin is at the user's initial head position instead of the floor.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Resource)]
+pub struct WebXrReferenceSpaceType(pub XrReferenceSpaceType);
+
+///
+/// The color encodings an xr framebuffer can have. It always holds 8 bit rgba values.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WebXrFramebufferEncoding {
+    Srgb,
+    Linear,
+}
+
+impl WebXrFramebufferEncoding {
+    pub fn texture_format(&self) -> TextureFormat {
+        match self {
+            WebXrFramebufferEncoding::Srgb => TextureFormat::Rgba8UnormSrgb,
+            WebXrFramebufferEncoding::Linear => TextureFormat::Rgba8Unorm,
+        }
+    }
+}
+
+///
+/// The color format of the running session's xr framebuffer.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Resource)]
+pub struct WebXrFramebufferFormat(pub TextureFormat);
+
+///
+/// The timing of the current xr frame. Times are in milliseconds on the clock of performance.now().
+///
+#[derive(Clone, Copy, Debug, Resource)]
+pub struct WebXrFrameTiming {
+    /// The timestamp passed to the xr animation frame callback.
+    pub time: f64,
+    /// The time the frame is expected to be shown on the display, if the browser provides it.
+    pub predicted_display_time: Option<f64>,
+    /// The interval since the previous xr frame. Zero on the first frame of a session.
+    pub interval: Duration,
+}
+
+///
+/// The refresh rates of the running session. Only present while a session is running.
+/// Setting target_frame_rate to one of the supported frame rates requests it from the browser.
+///
+#[derive(Clone, Default, Debug, Resource)]
+pub struct WebXrFrameRate {
+    /// Empty if the browser doesn't let the session choose a frame rate.
+    pub supported_frame_rates: Vec<f32>,
+    pub frame_rate: Option<f32>,
+    pub target_frame_rate: Option<f32>,
+}
+
+///
+/// Fixed foveated rendering of the base layer, from 0.0 for none to 1.0 for the strongest the device supports.
+/// Devices without support ignore it.
+///
+#[derive(Clone, Debug, Resource)]
+pub struct WebXrFoveation {
+    pub level: f32,
+    /// Raises the level while frames miss the session's frame rate and lowers it again once they keep up.
+    /// Without a reported frame rate, the shortest frame interval of the session is taken as the frame rate.
+    /// The browser's recommended viewport scale caps the scale, if it reports one.
+    pub automatic: bool,
+    pub min_level: f32,
+    pub max_level: f32,
+}
+
+impl Default for WebXrFoveation {
+    fn default() -> WebXrFoveation {
+        WebXrFoveation {
+            level: 0.0,
+            automatic: false,
+            min_level: 0.0,
+            max_level: 1.0,
+        }
+    }
+}
+
+fn set_xr_mode(
+    mut ended: EventReader<events::WebXrSessionEnded>,
+    mut event: EventReader<events::WebXrSessionInitialized>,
+    mut commands: Commands,
+) {
+    if !ended.is_empty() {
+        ended.clear();
+        commands.remove_resource::<bevy_xr::XrMode>();
+    }
+
     for event in event.read() {
         commands.insert_resource(match event.mode {
             XrMode::VR => bevy_xr::XrMode::VR,
@@ -111,3 +503,169 @@ fn set_xr_mode(mut event: EventReader<events::WebXrSessionInitialized>, mut comm
         })
     }
 }
+
+///
+/// Winit also updates the app between xr frames, e.g. for input events. Tracked entities keep their last pose then.
+///
+fn between_xr_frames(
+    frame: Option<NonSend<WebXrFrame>>,
+    framebuffer_format: Option<Res<WebXrFramebufferFormat>>,
+) -> bool {
+    frame.is_none() && framebuffer_format.is_some()
+}
+
+fn request_target_frame_rate(
+    frame_rate: Option<Res<WebXrFrameRate>>,
+    frame: Option<NonSend<WebXrFrame>>,
+    mut changed: Local<bool>,
+) {
+    let Some(frame_rate) = frame_rate else {
+        return;
+    };
+
+    // Changes between xr frames are requested with the next frame.
+    *changed |= frame_rate.is_changed();
+
+    let Some(frame) = frame else {
+        return;
+    };
+
+    if !std::mem::take(&mut *changed) || frame_rate.target_frame_rate == frame_rate.frame_rate {
+        return;
+    }
+
+    if let Some(target_frame_rate) = frame_rate.target_frame_rate {
+        info!("Requesting target frame rate: {}", target_frame_rate);
+
+        let promise = frame
+            .webxr_frame
+            .session()
+            .update_target_frame_rate(target_frame_rate);
+
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Err(err) = wasm_bindgen_futures::JsFuture::from(promise).await {
+                warn!("Failed to update target frame rate: {:?}", err);
+            }
+        });
+    }
+}
+
+///
+/// The measured xr frame intervals in seconds.
+///
+#[derive(Default)]
+struct FrameIntervals {
+    average: f32,
+    /// The shortest interval of the session, which is taken as the display's refresh interval.
+    min: f32,
+}
+
+///
+/// Greater while frames miss the session's frame rate, Less while they keep up and Equal in between.
+/// Browsers that don't report the frame rate are measured against the shortest frame interval of the session instead.
+/// None between xr frames and without a running session.
+///
+fn frame_time_pressure(
+    timing: Option<Res<WebXrFrameTiming>>,
+    frame_rate: Option<Res<WebXrFrameRate>>,
+    intervals: &mut FrameIntervals,
+) -> Option<Ordering> {
+    let timing = timing?;
+    // Only xr frames change the timing, the updates between them don't measure anything.
+    if !timing.is_changed() {
+        return None;
+    }
+    // The first frame of a session, which may run on another display.
+    if timing.interval.is_zero() {
+        *intervals = FrameIntervals::default();
+        return None;
+    }
+
+    let interval = timing.interval.as_secs_f32();
+    if intervals.min == 0.0 || interval < intervals.min {
+        intervals.min = interval;
+    }
+
+    let target_interval = frame_rate
+        .and_then(|frame_rate| frame_rate.frame_rate)
+        .map(|frame_rate| 1.0 / frame_rate)
+        .unwrap_or(intervals.min);
+
+    // Smoothed, so single hitches don't toggle anything.
+    intervals.average = intervals.average * 0.9 + interval * 0.1;
+
+    Some(if intervals.average > target_interval * 1.2 {
+        Ordering::Greater
+    } else if intervals.average < target_interval * 1.05 {
+        Ordering::Less
+    } else {
+        Ordering::Equal
+    })
+}
+
+fn update_automatic_foveation(
+    mut foveation: ResMut<WebXrFoveation>,
+    timing: Option<Res<WebXrFrameTiming>>,
+    frame_rate: Option<Res<WebXrFrameRate>>,
+    mut intervals: Local<FrameIntervals>,
+) {
+    if !foveation.automatic {
+        return;
+    }
+
+    let level = match frame_time_pressure(timing, frame_rate, &mut intervals) {
+        Some(Ordering::Greater) => foveation.level + 0.02,
+        Some(Ordering::Less) => foveation.level - 0.01,
+        _ => return,
+    }
+    .clamp(foveation.min_level, foveation.max_level);
+
+    if level != foveation.level {
+        foveation.level = level;
+    }
+}
+
+fn update_automatic_viewport_scale(
+    mut viewport_scale: ResMut<WebXrViewportScale>,
+    timing: Option<Res<WebXrFrameTiming>>,
+    frame_rate: Option<Res<WebXrFrameRate>>,
+    mut intervals: Local<FrameIntervals>,
+) {
+    if !viewport_scale.automatic {
+        return;
+    }
+
+    let scale = match frame_time_pressure(timing, frame_rate, &mut intervals) {
+        Some(Ordering::Greater) => viewport_scale.scale - 0.02,
+        Some(Ordering::Less) => viewport_scale.scale + 0.01,
+        _ => return,
+    }
+    .clamp(viewport_scale.min_scale, 1.0);
+
+    if scale != viewport_scale.scale {
+        viewport_scale.scale = scale;
+    }
+}
+
+fn apply_foveation(
+    foveation: Res<WebXrFoveation>,
+    mut initialized: EventReader<events::WebXrSessionInitialized>,
+    frame: Option<NonSend<WebXrFrame>>,
+    mut changed: Local<bool>,
+) {
+    // New sessions come with a new base layer.
+    let initialized = initialized.read().count() > 0;
+
+    // Sessions are initialized and foveation may change between xr frames, so it is applied with the next frame.
+    *changed |= foveation.is_changed() || initialized;
+
+    if frame.is_none() || !std::mem::take(&mut *changed) {
+        return;
+    }
+
+    if let Some(base_layer) =
+        frame.and_then(|frame| frame.webxr_frame.session().render_state().base_layer())
+    {
+        base_layer.set_fixed_foveation(Some(foveation.level.clamp(0.0, 1.0)));
+    }
+}
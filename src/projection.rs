@@ -1,21 +1,40 @@
-use bevy::{math::Vec3A, prelude::*, render::camera::CameraProjection};
+use bevy::{
+    math::Vec3A,
+    prelude::*,
+    render::{
+        camera::CameraProjection,
+        primitives::{Frustum, HalfSpace},
+    },
+};
 
 #[derive(Component, Debug, Clone, Reflect)]
 #[reflect(Component, Default)]
 pub struct WebXrProjection {
     pub projection_matrix: Mat4,
+    /// The far plane is lost when the matrix is converted to an infinite projection, so it is kept separately.
+    pub far: f32,
 }
 
-fn transform_matrix(matrix: &mut Mat4) {
+/// Converts an OpenGL projection matrix to bevy's reversed depth and returns its far plane.
+fn transform_matrix(matrix: &mut Mat4) -> f32 {
+    let near = matrix.w_axis.z / (matrix.z_axis.z - 1.0);
+    let far = matrix.w_axis.z / (matrix.z_axis.z + 1.0);
+
+    // Infinite reversed projection like Mat4::perspective_infinite_reverse_rh.
     matrix.z_axis.z = 0.0;
-    matrix.w_axis.z = -matrix.w_axis.z;
+    matrix.w_axis.z = near;
+
+    far
 }
 
 impl From<Vec<f32>> for WebXrProjection {
     fn from(value: Vec<f32>) -> Self {
         let mut projection_matrix = Mat4::from_cols_slice(&value);
-        transform_matrix(&mut projection_matrix);
-        WebXrProjection { projection_matrix }
+        let far = transform_matrix(&mut projection_matrix);
+        WebXrProjection {
+            projection_matrix,
+            far,
+        }
     }
 }
 
@@ -29,16 +48,139 @@ impl Default for WebXrProjection {
                 z_axis: Vec4::new(0.0, 0.0, 0.0, -1.0),
                 w_axis: Vec4::new(0.0, 0.0, 0.1, 0.0),
             },
+            far: 1000.0,
         }
     }
 }
 
 impl WebXrProjection {
+    ///
+    /// Creates an infinite reversed projection from the angles of the frustum's sides in radians,
+    /// the same way XR runtimes describe the field of view of an eye.
+    /// Left and bottom are usually negative.
+    ///
+    pub fn new(left: f32, right: f32, top: f32, bottom: f32, near: f32, far: f32) -> Self {
+        let (left, right, top, bottom) = (left.tan(), right.tan(), top.tan(), bottom.tan());
+        let width = right - left;
+        let height = top - bottom;
+
+        WebXrProjection {
+            projection_matrix: Mat4 {
+                x_axis: Vec4::new(2.0 / width, 0.0, 0.0, 0.0),
+                y_axis: Vec4::new(0.0, 2.0 / height, 0.0, 0.0),
+                z_axis: Vec4::new((right + left) / width, (top + bottom) / height, 0.0, -1.0),
+                w_axis: Vec4::new(0.0, 0.0, near, 0.0),
+            },
+            far,
+        }
+    }
+
     pub fn update_matrix(&mut self, value: Vec<f32>) {
         let mut matrix = Mat4::from_cols_slice(&value);
-        transform_matrix(&mut matrix);
+        self.far = transform_matrix(&mut matrix);
         self.projection_matrix = matrix;
     }
+
+    /// The tangents of the left, right, top and bottom angles of the frustum.
+    fn tangents(&self) -> (f32, f32, f32, f32) {
+        let matrix = &self.projection_matrix;
+        (
+            (matrix.z_axis.x - 1.0) / matrix.x_axis.x,
+            (matrix.z_axis.x + 1.0) / matrix.x_axis.x,
+            (matrix.z_axis.y + 1.0) / matrix.y_axis.y,
+            (matrix.z_axis.y - 1.0) / matrix.y_axis.y,
+        )
+    }
+
+    /// The angle of the left side of the frustum in radians, usually negative.
+    pub fn left(&self) -> f32 {
+        self.tangents().0.atan()
+    }
+
+    /// The angle of the right side of the frustum in radians.
+    pub fn right(&self) -> f32 {
+        self.tangents().1.atan()
+    }
+
+    /// The angle of the top side of the frustum in radians.
+    pub fn top(&self) -> f32 {
+        self.tangents().2.atan()
+    }
+
+    /// The angle of the bottom side of the frustum in radians, usually negative.
+    pub fn bottom(&self) -> f32 {
+        self.tangents().3.atan()
+    }
+
+    pub fn near(&self) -> f32 {
+        self.projection_matrix.w_axis.z / (self.projection_matrix.z_axis.z + 1.0)
+    }
+
+    /// The vertical field of view in radians.
+    pub fn fov(&self) -> f32 {
+        self.top() - self.bottom()
+    }
+
+    /// Width divided by height of the frustum, like the aspect ratio of a PerspectiveProjection.
+    pub fn aspect_ratio(&self) -> f32 {
+        self.projection_matrix.y_axis.y / self.projection_matrix.x_axis.x
+    }
+
+    ///
+    /// A frustum containing the frusta of both eyes, so culling can be done once for a stereo pair.
+    /// It is built from the outer planes of the eyes and assumes they look in the same direction,
+    /// like on most headsets. Their views may be asymmetric in any way.
+    ///
+    pub fn stereo_frustum(
+        left: (&GlobalTransform, &WebXrProjection),
+        right: (&GlobalTransform, &WebXrProjection),
+    ) -> Frustum {
+        let frustum = |(transform, projection): (&GlobalTransform, &WebXrProjection)| {
+            // The same as bevy's update_frusta.
+            let view_projection =
+                projection.get_projection_matrix() * transform.compute_matrix().inverse();
+            Frustum::from_view_projection_custom_far(
+                &view_projection,
+                &transform.translation(),
+                &transform.back(),
+                projection.far(),
+            )
+        };
+        let left_frustum = frustum(left);
+        let right_frustum = frustum(right);
+
+        let outer = |left_outer: bool, index: usize| {
+            if left_outer {
+                left_frustum.half_spaces[index]
+            } else {
+                right_frustum.half_spaces[index]
+            }
+        };
+
+        // The side with the wider angle is outer, but it starts at its own eye. It is moved out
+        // to the other eye too, as the other eye's view starts beyond it where the angles differ.
+        let outer_side = |left_outer: bool, index: usize| {
+            let (half_space, other_eye) = if left_outer {
+                (left_frustum.half_spaces[index], right.0.translation_vec3a())
+            } else {
+                (right_frustum.half_spaces[index], left.0.translation_vec3a())
+            };
+            let normal = half_space.normal();
+            HalfSpace::new(normal.extend(half_space.d().max(-normal.dot(other_eye))))
+        };
+
+        // Left, right, bottom, top, near, far.
+        Frustum {
+            half_spaces: [
+                outer_side(left.1.left() <= right.1.left(), 0),
+                outer_side(left.1.right() >= right.1.right(), 1),
+                outer_side(left.1.bottom() <= right.1.bottom(), 2),
+                outer_side(left.1.top() >= right.1.top(), 3),
+                outer(left.1.near() <= right.1.near(), 4),
+                outer(left.1.far() >= right.1.far(), 5),
+            ],
+        }
+    }
 }
 
 impl CameraProjection for WebXrProjection {
@@ -50,30 +192,218 @@ impl CameraProjection for WebXrProjection {
     fn update(&mut self, _width: f32, _height: f32) {}
 
     fn far(&self) -> f32 {
-        let far = self.projection_matrix.to_cols_array()[14]
-            / (self.projection_matrix.to_cols_array()[10] + 1.0);
-
-        far
-    }
-
-    fn get_frustum_corners(&self, _z_near: f32, _z_far: f32) -> [bevy::math::Vec3A; 8] {
-        let ndc_corners = [
-            Vec3A::new(1.0, -1.0, 1.0),   // Bottom-right far
-            Vec3A::new(1.0, 1.0, 1.0),    // Top-right far
-            Vec3A::new(-1.0, 1.0, 1.0),   // Top-left far
-            Vec3A::new(-1.0, -1.0, 1.0),  // Bottom-left far
-            Vec3A::new(1.0, -1.0, -1.0),  // Bottom-right near
-            Vec3A::new(1.0, 1.0, -1.0),   // Top-right near
-            Vec3A::new(-1.0, 1.0, -1.0),  // Top-left near
-            Vec3A::new(-1.0, -1.0, -1.0), // Bottom-left near
-        ];
-
-        let mut view_space_corners = [Vec3A::ZERO; 8];
-        let inverse_matrix = self.projection_matrix.inverse();
-        for (i, corner) in ndc_corners.into_iter().enumerate() {
-            view_space_corners[i] = inverse_matrix.transform_point3a(corner);
+        self.far
+    }
+
+    fn get_frustum_corners(&self, z_near: f32, z_far: f32) -> [Vec3A; 8] {
+        let (left, right, top, bottom) = self.tangents();
+        let near = z_near.abs();
+        let far = z_far.abs();
+
+        // The same order as PerspectiveProjection, which the shadow cascades rely on.
+        [
+            Vec3A::new(right * near, bottom * near, z_near), // bottom right
+            Vec3A::new(right * near, top * near, z_near),    // top right
+            Vec3A::new(left * near, top * near, z_near),     // top left
+            Vec3A::new(left * near, bottom * near, z_near),  // bottom left
+            Vec3A::new(right * far, bottom * far, z_far),    // bottom right
+            Vec3A::new(right * far, top * far, z_far),       // top right
+            Vec3A::new(left * far, top * far, z_far),        // top left
+            Vec3A::new(left * far, bottom * far, z_far),     // bottom left
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::render::primitives::Sphere;
+
+    /// Approximately the field of view of a Quest 2's left eye in degrees (left, right, top, bottom).
+    const QUEST_2_LEFT: (f32, f32, f32, f32) = (-52.0, 42.0, 47.0, -53.0);
+    /// Approximately the field of view of a phone's camera in handheld AR.
+    const PHONE_AR: (f32, f32, f32, f32) = (-30.0, 30.0, 45.0, -45.0);
+
+    /// The column major OpenGL matrix an XRView reports for the given angles and depth range.
+    fn xr_view_matrix(fov: (f32, f32, f32, f32), depth_near: f32, depth_far: f32) -> Vec<f32> {
+        let left = fov.0.to_radians().tan() * depth_near;
+        let right = fov.1.to_radians().tan() * depth_near;
+        let top = fov.2.to_radians().tan() * depth_near;
+        let bottom = fov.3.to_radians().tan() * depth_near;
+
+        let (width, height, depth) = (right - left, top - bottom, depth_far - depth_near);
+        Mat4 {
+            x_axis: Vec4::new(2.0 * depth_near / width, 0.0, 0.0, 0.0),
+            y_axis: Vec4::new(0.0, 2.0 * depth_near / height, 0.0, 0.0),
+            z_axis: Vec4::new(
+                (right + left) / width,
+                (top + bottom) / height,
+                -(depth_far + depth_near) / depth,
+                -1.0,
+            ),
+            w_axis: Vec4::new(0.0, 0.0, -2.0 * depth_far * depth_near / depth, 0.0),
+        }
+        .to_cols_array()
+        .to_vec()
+    }
+
+    fn assert_approx(a: f32, b: f32) {
+        assert!((a - b).abs() <= 1e-3 * b.abs().max(1.0), "{a} != {b}");
+    }
+
+    fn assert_fov(projection: &WebXrProjection, fov: (f32, f32, f32, f32)) {
+        assert_approx(projection.left(), fov.0.to_radians());
+        assert_approx(projection.right(), fov.1.to_radians());
+        assert_approx(projection.top(), fov.2.to_radians());
+        assert_approx(projection.bottom(), fov.3.to_radians());
+    }
+
+    fn depth(projection: &WebXrProjection, point: Vec3) -> f32 {
+        projection.projection_matrix.project_point3(point).z
+    }
+
+    #[test]
+    fn decomposes_xr_view_matrix() {
+        for fov in [QUEST_2_LEFT, PHONE_AR] {
+            let projection = WebXrProjection::from(xr_view_matrix(fov, 0.1, 1000.0));
+
+            assert_fov(&projection, fov);
+            assert_approx(projection.near(), 0.1);
+            assert_approx(projection.far(), 1000.0);
+            assert_approx(projection.fov(), (fov.2 - fov.3).to_radians());
+            assert_approx(
+                projection.aspect_ratio(),
+                (fov.1.to_radians().tan() - fov.0.to_radians().tan())
+                    / (fov.2.to_radians().tan() - fov.3.to_radians().tan()),
+            );
+
+            // Infinite reversed depth like bevy's PerspectiveProjection.
+            assert_approx(depth(&projection, Vec3::new(0.0, 0.0, -0.1)), 1.0);
+            assert_approx(depth(&projection, Vec3::new(0.0, 0.0, -1.0)), 0.1);
         }
+    }
+
+    #[test]
+    fn new_matches_xr_view_matrix() {
+        let (left, right, top, bottom) = QUEST_2_LEFT;
+        let projection = WebXrProjection::new(
+            left.to_radians(),
+            right.to_radians(),
+            top.to_radians(),
+            bottom.to_radians(),
+            0.1,
+            1000.0,
+        );
+        let xr_projection = WebXrProjection::from(xr_view_matrix(QUEST_2_LEFT, 0.1, 1000.0));
+
+        assert!(projection
+            .projection_matrix
+            .abs_diff_eq(xr_projection.projection_matrix, 1e-4));
+        assert_eq!(projection.far(), 1000.0);
+    }
+
+    #[test]
+    fn frustum_corners_lie_on_the_requested_slice() {
+        for fov in [QUEST_2_LEFT, PHONE_AR] {
+            let projection = WebXrProjection::from(xr_view_matrix(fov, 0.1, 1000.0));
+            let corners = projection.get_frustum_corners(-0.5, -20.0);
+
+            let ndc = [
+                Vec2::new(1.0, -1.0),
+                Vec2::new(1.0, 1.0),
+                Vec2::new(-1.0, 1.0),
+                Vec2::new(-1.0, -1.0),
+            ];
+            for (i, corner) in corners.into_iter().enumerate() {
+                let z = if i < 4 { -0.5 } else { -20.0 };
+                let projected = projection
+                    .projection_matrix
+                    .project_point3(Vec3::from(corner));
+
+                assert_eq!(corner.z, z);
+                assert_approx(projected.x, ndc[i % 4].x);
+                assert_approx(projected.y, ndc[i % 4].y);
+                assert_approx(projected.z, 0.1 / -z);
+            }
+        }
+    }
+
+    fn assert_stereo_frustum_contains_both_eyes(
+        left_fov: (f32, f32, f32, f32),
+        right_fov: (f32, f32, f32, f32),
+    ) {
+        let left_projection = WebXrProjection::from(xr_view_matrix(left_fov, 0.1, 1000.0));
+        let right_projection = WebXrProjection::from(xr_view_matrix(right_fov, 0.1, 1000.0));
+        let head = GlobalTransform::from_xyz(0.0, 1.6, 0.0);
+        let left_transform = head * GlobalTransform::from_xyz(-0.032, 0.0, 0.0);
+        let right_transform = head * GlobalTransform::from_xyz(0.032, 0.0, 0.0);
+
+        let frustum = WebXrProjection::stereo_frustum(
+            (&left_transform, &left_projection),
+            (&right_transform, &right_projection),
+        );
+
+        let contains = |point: Vec3| {
+            frustum.intersects_sphere(
+                &Sphere {
+                    center: point.into(),
+                    radius: 0.0,
+                },
+                true,
+            )
+        };
+
+        for (transform, projection) in [
+            (&left_transform, &left_projection),
+            (&right_transform, &right_projection),
+        ] {
+            // Slightly inside the corners of each eye, close by and far away.
+            for corner in projection.get_frustum_corners(-0.2, -100.0) {
+                let inside = corner * Vec3A::new(0.99, 0.99, 1.0) + Vec3A::new(0.0, 0.0, -0.01);
+                assert!(contains(transform.transform_point(inside.into())));
+            }
+        }
+
+        // Slightly outside the outer sides of the eyes.
+        let left_outside = left_transform.transform_point(Vec3::new(
+            left_fov
+                .0
+                .to_radians()
+                .tan()
+                .min(right_fov.0.to_radians().tan())
+                * 10.0
+                - 0.2,
+            0.0,
+            -10.0,
+        ));
+        let right_outside = right_transform.transform_point(Vec3::new(
+            right_fov
+                .1
+                .to_radians()
+                .tan()
+                .max(left_fov.1.to_radians().tan())
+                * 10.0
+                + 0.2,
+            0.0,
+            -10.0,
+        ));
+        let behind = head.transform_point(Vec3::new(0.0, 0.0, 0.05));
+        let too_far = head.transform_point(Vec3::new(0.0, 0.0, -1001.0));
+
+        for point in [left_outside, right_outside, behind, too_far] {
+            assert!(!contains(point));
+        }
+    }
+
+    #[test]
+    fn stereo_frustum_contains_both_eyes() {
+        let (left, right, top, bottom) = QUEST_2_LEFT;
+        assert_stereo_frustum_contains_both_eyes(QUEST_2_LEFT, (-right, -left, top, bottom));
+    }
 
-        view_space_corners
+    #[test]
+    fn stereo_frustum_contains_eyes_with_wider_inner_sides() {
+        let (left, right, top, bottom) = QUEST_2_LEFT;
+        assert_stereo_frustum_contains_both_eyes((-right, -left, top, bottom), QUEST_2_LEFT);
     }
 }
@@ -0,0 +1,220 @@
+use bevy::{
+    asset::load_internal_asset,
+    core_pipeline::{core_3d, fullscreen_vertex_shader::fullscreen_shader_vertex_state},
+    ecs::query::QueryItem,
+    prelude::*,
+    render::{
+        camera::RenderTarget,
+        extract_component::{ExtractComponent, ExtractComponentPlugin},
+        render_graph::{
+            NodeRunError, RenderGraphApp, RenderGraphContext, ViewNode, ViewNodeRunner,
+        },
+        render_resource::{
+            BindGroupEntries, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry,
+            BindingType, CachedRenderPipelineId, ColorTargetState, ColorWrites, FragmentState,
+            MultisampleState, Operations, PipelineCache, PrimitiveState, RenderPassColorAttachment,
+            RenderPassDescriptor, RenderPipelineDescriptor, Sampler, SamplerBindingType,
+            SamplerDescriptor, ShaderStages, TextureFormat, TextureSampleType,
+            TextureViewDimension,
+        },
+        renderer::{RenderContext, RenderDevice},
+        texture::BevyDefault,
+        view::ViewTarget,
+        RenderApp,
+    },
+};
+
+use crate::{tracked::camera::FRAMEBUFFER_HANDLE, WebXrFramebufferFormat};
+
+const SRGB_CONVERSION_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(271828182845904523536028747135266249);
+const SRGB_CONVERSION: &str = "webxr_srgb_conversion";
+
+///
+/// Cameras rendering into an xr framebuffer that doesn't encode to srgb on its own.
+/// Their output gets converted from linear to srgb after bevy's post processing.
+///
+#[derive(Component, Clone, Copy, ExtractComponent)]
+pub(crate) struct SrgbConversion;
+
+pub(crate) struct SrgbConversionPlugin;
+
+impl Plugin for SrgbConversionPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            SRGB_CONVERSION_SHADER_HANDLE,
+            "srgb_conversion.wgsl",
+            Shader::from_wgsl
+        );
+
+        app.add_plugins(ExtractComponentPlugin::<SrgbConversion>::default());
+
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+
+        render_app
+            .add_render_graph_node::<ViewNodeRunner<SrgbConversionNode>>(
+                core_3d::graph::NAME,
+                SRGB_CONVERSION,
+            )
+            .add_render_graph_edges(
+                core_3d::graph::NAME,
+                &[
+                    core_3d::graph::node::END_MAIN_PASS_POST_PROCESSING,
+                    SRGB_CONVERSION,
+                    core_3d::graph::node::UPSCALING,
+                ],
+            );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+
+        render_app.init_resource::<SrgbConversionPipeline>();
+    }
+}
+
+pub(crate) fn update_srgb_conversion(
+    framebuffer_format: Option<Res<WebXrFramebufferFormat>>,
+    cameras: Query<(Entity, &Camera, Has<SrgbConversion>)>,
+    mut commands: Commands,
+) {
+    let convert = framebuffer_format.is_some_and(|format| !format.0.is_srgb());
+
+    for (entity, camera, has_conversion) in cameras.iter() {
+        let renders_to_framebuffer = matches!(
+            camera.target,
+            RenderTarget::TextureView(handle) if handle == FRAMEBUFFER_HANDLE
+        );
+
+        if convert && renders_to_framebuffer {
+            if !has_conversion {
+                commands.entity(entity).insert(SrgbConversion);
+            }
+        } else if has_conversion {
+            commands.entity(entity).remove::<SrgbConversion>();
+        }
+    }
+}
+
+#[derive(Resource)]
+struct SrgbConversionPipeline {
+    layout: BindGroupLayout,
+    sampler: Sampler,
+    pipeline_id: CachedRenderPipelineId,
+}
+
+impl FromWorld for SrgbConversionPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+
+        let layout = render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("webxr_srgb_conversion_bind_group_layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let sampler = render_device.create_sampler(&SamplerDescriptor::default());
+
+        let pipeline_id =
+            world
+                .resource_mut::<PipelineCache>()
+                .queue_render_pipeline(RenderPipelineDescriptor {
+                    label: Some("webxr_srgb_conversion_pipeline".into()),
+                    layout: vec![layout.clone()],
+                    vertex: fullscreen_shader_vertex_state(),
+                    fragment: Some(FragmentState {
+                        shader: SRGB_CONVERSION_SHADER_HANDLE,
+                        shader_defs: vec![],
+                        entry_point: "fragment".into(),
+                        targets: vec![Some(ColorTargetState {
+                            format: TextureFormat::bevy_default(),
+                            blend: None,
+                            write_mask: ColorWrites::ALL,
+                        })],
+                    }),
+                    primitive: PrimitiveState::default(),
+                    depth_stencil: None,
+                    multisample: MultisampleState::default(),
+                    push_constant_ranges: vec![],
+                });
+
+        SrgbConversionPipeline {
+            layout,
+            sampler,
+            pipeline_id,
+        }
+    }
+}
+
+#[derive(Default)]
+struct SrgbConversionNode;
+
+impl ViewNode for SrgbConversionNode {
+    type ViewQuery = (&'static ViewTarget, &'static SrgbConversion);
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        (view_target, _): QueryItem<Self::ViewQuery>,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        // The pipeline only targets the default format of ldr cameras, which is what the xr cameras use.
+        if view_target.is_hdr() {
+            return Ok(());
+        }
+
+        let srgb_conversion_pipeline = world.resource::<SrgbConversionPipeline>();
+        let Some(pipeline) = world
+            .resource::<PipelineCache>()
+            .get_render_pipeline(srgb_conversion_pipeline.pipeline_id)
+        else {
+            return Ok(());
+        };
+
+        let post_process = view_target.post_process_write();
+
+        let bind_group = render_context.render_device().create_bind_group(
+            "webxr_srgb_conversion_bind_group",
+            &srgb_conversion_pipeline.layout,
+            &BindGroupEntries::sequential((post_process.source, &srgb_conversion_pipeline.sampler)),
+        );
+
+        let mut render_pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
+            label: Some("webxr_srgb_conversion_pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: post_process.destination,
+                resolve_target: None,
+                ops: Operations::default(),
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_render_pipeline(pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+
+        Ok(())
+    }
+}
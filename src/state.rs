@@ -0,0 +1,59 @@
+use bevy::prelude::*;
+
+use crate::XrMode;
+
+///
+/// The state of the webxr session. Driven by the runner, so OnEnter and OnExit schedules can react to sessions.
+///
+#[derive(States, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum WebXrSessionState {
+    /// WebXr is not available or none of the supported modes is enabled in the settings.
+    #[default]
+    Unsupported,
+    /// A session can be requested.
+    Available,
+    /// A session has been requested and is being initialized.
+    Requesting,
+    /// A session is running and visible.
+    Running { mode: XrMode },
+    /// A session is running but hidden, e.g. while the headset is taken off.
+    Hidden,
+    /// The running session is being ended.
+    Ending,
+}
+
+impl WebXrSessionState {
+    pub fn mode(&self) -> Option<XrMode> {
+        match self {
+            WebXrSessionState::Running { mode } => Some(*mode),
+            _ => None,
+        }
+    }
+}
+
+/// Run condition that is true while a session of any mode is running and visible.
+pub fn in_xr_session() -> impl FnMut(Res<State<WebXrSessionState>>) -> bool + Clone {
+    move |state: Res<State<WebXrSessionState>>| state.mode().is_some()
+}
+
+/// Run condition that is true while an immersive vr or ar session is running and visible.
+pub fn in_immersive_session() -> impl FnMut(Res<State<WebXrSessionState>>) -> bool + Clone {
+    move |state: Res<State<WebXrSessionState>>| {
+        matches!(state.mode(), Some(XrMode::VR) | Some(XrMode::AR))
+    }
+}
+
+/// Run condition that is true while a vr session is running and visible.
+pub fn in_vr() -> impl FnMut(Res<State<WebXrSessionState>>) -> bool + Clone {
+    move |state: Res<State<WebXrSessionState>>| state.mode() == Some(XrMode::VR)
+}
+
+/// Run condition that is true while an ar session is running and visible.
+pub fn in_ar() -> impl FnMut(Res<State<WebXrSessionState>>) -> bool + Clone {
+    move |state: Res<State<WebXrSessionState>>| state.mode() == Some(XrMode::AR)
+}
+
+/// Run condition that is true while an inline session is running and visible.
+pub fn in_inline_session() -> impl FnMut(Res<State<WebXrSessionState>>) -> bool + Clone {
+    move |state: Res<State<WebXrSessionState>>| state.mode() == Some(XrMode::Inline)
+}
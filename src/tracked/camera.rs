@@ -1,23 +1,46 @@
 use bevy::{
-    core_pipeline::clear_color::ClearColorConfig, prelude::*, render::{
-        camera::{ManualTextureView, ManualTextureViewHandle, ManualTextureViews, Viewport},
+    prelude::*, render::{
+        camera::{ManualTextureViewHandle, ManualTextureViews, RenderTarget, Viewport},
         renderer::RenderDevice,
-    }
+    }, window::WindowRef
 };
 use bevy_xr::{
     handedness::{Handedness, LeftHanded, RightHanded}, head::XrEye, render::FlipView, space::XrOrigin, window::XrWindow, XrActive, XrLocal
 };
-use web_sys::XrView;
-use wgpu::TextureUsages;
+use web_sys::{WebGlFramebuffer, XrView, XrWebGlLayer};
 
 use crate::{
+    clear_color::{is_passthrough, view_clear_color},
     dom_point::{dom_point_to_quat, dom_point_to_vec3},
+    events::WebXrSessionEnded,
     projection::WebXrProjection,
-    WebXrFrame,
+    WebXrClearColor, WebXrEnvironmentBlendMode, WebXrFrame, WebXrFramebufferFormat, WebXrSecondaryViews, WebXrViewportScale, XrHead, XrSecondaryView,
 };
 
 pub(crate) const FRAMEBUFFER_HANDLE: ManualTextureViewHandle = ManualTextureViewHandle(5724242);
-const TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+/// Inline views render into the window after the app's own cameras.
+const INLINE_CAMERA_ORDER: isize = 100;
+/// Secondary views render before the eyes, as the last eye flips the whole xr framebuffer.
+const SECONDARY_VIEW_CAMERA_ORDER: isize = -100;
+
+///
+/// The framebuffer that the texture view under FRAMEBUFFER_HANDLE currently wraps.
+/// The texture view is only rebuilt when the framebuffer, its size or the base layer changes.
+///
+#[derive(Default)]
+pub(crate) struct XrFramebufferCache(Option<(WebGlFramebuffer, XrWebGlLayer, UVec2)>);
+
+impl XrFramebufferCache {
+    fn is_current(&self, framebuffer: &WebGlFramebuffer, base_layer: &XrWebGlLayer) -> bool {
+        matches!(
+            &self.0,
+            Some((cached_framebuffer, cached_base_layer, size))
+                if cached_framebuffer == framebuffer
+                    && cached_base_layer == base_layer
+                    && *size == UVec2::new(base_layer.framebuffer_width(), base_layer.framebuffer_height())
+        )
+    }
+}
 
 pub(crate) fn update_xr_cameras(
     xr_frame: Option<NonSend<WebXrFrame>>,
@@ -61,13 +84,61 @@ pub(crate) fn update_xr_cameras(
             &mut Camera,
             &mut WebXrProjection,
             &mut XrActive,
+            Has<FlipView>,
         ),
         (With<XrWindow>, With<XrLocal>, Without<XrEye>),
     >,
     render_device: Res<RenderDevice>,
     mut texture_views: ResMut<ManualTextureViews>,
+    mut framebuffer_cache: NonSendMut<XrFramebufferCache>,
+    framebuffer_format: Option<Res<WebXrFramebufferFormat>>,
+    mut heads: Query<(Entity, &mut Transform, &mut XrActive), (With<XrHead>, With<XrLocal>, Without<XrEye>, Without<XrWindow>)>,
+    viewport_scale: Res<WebXrViewportScale>,
+    mut secondary_views: Query<
+        (
+            Entity,
+            &mut Transform,
+            &mut Camera,
+            &mut WebXrProjection,
+            &mut XrActive,
+        ),
+        (
+            With<XrSecondaryView>,
+            With<XrLocal>,
+            Without<XrEye>,
+            Without<XrWindow>,
+            Without<XrHead>,
+        ),
+    >,
+    secondary_views_settings: Res<WebXrSecondaryViews>,
+    clear_color: Res<WebXrClearColor>,
+    blend_mode: Option<Res<WebXrEnvironmentBlendMode>>,
     mut commands: Commands,
 ) {
+    // Winit also updates between xr frames, e.g. for input events. Only xr frames can render into the xr framebuffer,
+    // while inline windows keep rendering into the canvas with their last pose.
+    if xr_frame.is_none() && framebuffer_format.is_some() {
+        for (_, _, mut camera, _, _) in eyes_left.iter_mut() {
+            camera.is_active = false;
+        }
+
+        for (_, _, mut camera, _, _) in eyes_right.iter_mut() {
+            camera.is_active = false;
+        }
+
+        for (_, _, mut camera, _, _, flip_view) in windows.iter_mut() {
+            if flip_view {
+                camera.is_active = false;
+            }
+        }
+
+        for (_, _, mut camera, _, _) in secondary_views.iter_mut() {
+            camera.is_active = false;
+        }
+
+        return;
+    }
+
     if !origin.is_empty() {
         if let Some(frame) = xr_frame {
             if let Some(viewer_pose) = frame
@@ -76,6 +147,25 @@ pub(crate) fn update_xr_cameras(
             {
                 let views = viewer_pose.views();
 
+                //Update head:
+                let head_transform = Transform {
+                    translation: dom_point_to_vec3(&viewer_pose.transform().position()),
+                    rotation: dom_point_to_quat(&viewer_pose.transform().orientation()),
+                    ..default()
+                };
+
+                let head = if let Ok((head, mut transform, mut active)) = heads.get_single_mut() {
+                    *transform = head_transform;
+                    active.0 = true;
+                    head
+                } else {
+                    let head = commands
+                        .spawn((SpatialBundle::from_transform(head_transform), XrHead, XrLocal, XrActive(true)))
+                        .id();
+                    commands.entity(origin.single()).add_child(head);
+                    head
+                };
+
                 if views.length() > 0 {
                     if let Some(base_layer) =
                         frame.webxr_frame.session().render_state().base_layer()
@@ -85,88 +175,150 @@ pub(crate) fn update_xr_cameras(
                         {
                             //Update framebuffer:
 
-                            let framebuffer: web_sys::WebGlFramebuffer = framebuffer.into();
+                            // Inline sessions have no framebuffer of their own and render straight into the canvas.
+                            let inline = framebuffer.is_null();
 
-                            let texture = unsafe {
-                                render_device
-                                    .wgpu_device()
-                                    .create_texture_from_hal::<wgpu_hal::gles::Api>(
-                                        wgpu_hal::gles::Texture {
-                                            inner:
-                                                wgpu_hal::gles::TextureInner::ExternalFramebuffer {
-                                                    inner: framebuffer,
-                                                },
-                                            mip_level_count: 1,
-                                            array_layer_count: 1,
-                                            format: TEXTURE_FORMAT,
-                                            format_desc: wgpu_hal::gles::TextureFormatDesc {
-                                                internal: glow::RGBA, // TODO: Test alternatives.
-                                                external: glow::RGBA,
-                                                data_type: glow::UNSIGNED_BYTE,
-                                            },
-                                            copy_size: wgpu_hal::CopyExtent {
-                                                width: base_layer.framebuffer_width(),
-                                                height: base_layer.framebuffer_height(),
-                                                depth: 1,
-                                            },
-                                            drop_guard: None,
-                                            is_cubemap: false,
-                                        },
-                                        &wgpu::TextureDescriptor {
-                                            label: Some("webxr framebuffer (color)"),
-                                            size: wgpu::Extent3d {
-                                                width: base_layer.framebuffer_width(),
-                                                height: base_layer.framebuffer_height(),
-                                                depth_or_array_layers: 1,
-                                            },
-                                            mip_level_count: 1,
-                                            sample_count: 1,
-                                            dimension: wgpu::TextureDimension::D2,
-                                            format: TEXTURE_FORMAT,
-                                            view_formats: &[TEXTURE_FORMAT],
-                                            usage: TextureUsages::RENDER_ATTACHMENT
-                                                | TextureUsages::TEXTURE_BINDING
-                                                | TextureUsages::COPY_SRC,
-                                        },
-                                    )
+                            let target = if inline {
+                                RenderTarget::Window(WindowRef::Primary)
+                            } else {
+                                let framebuffer: WebGlFramebuffer = framebuffer.into();
+                                if !framebuffer_cache.is_current(&framebuffer, &base_layer)
+                                    || !texture_views.contains_key(&FRAMEBUFFER_HANDLE)
+                                {
+                                    info!("Creating framebuffer texture view: {}x{}", base_layer.framebuffer_width(), base_layer.framebuffer_height());
+                                    insert_framebuffer_texture_view(
+                                        framebuffer.clone(),
+                                        framebuffer_format.as_ref().map_or(wgpu::TextureFormat::Rgba8UnormSrgb, |format| format.0),
+                                        &base_layer,
+                                        &render_device,
+                                        &mut texture_views,
+                                    );
+                                    framebuffer_cache.0 = Some((
+                                        framebuffer,
+                                        base_layer.clone(),
+                                        UVec2::new(base_layer.framebuffer_width(), base_layer.framebuffer_height()),
+                                    ));
+                                }
+                                RenderTarget::TextureView(FRAMEBUFFER_HANDLE)
                             };
 
-                            // let texture_view = texture.create_view(&wgpu::TextureViewDescriptor {
-                            //     label: Some("webxr_framebuffer_color"),
-                            //     format: Some(TEXTURE_FORMAT),
-                            //     dimension: Some(wgpu::TextureViewDimension::D2),
-                            //     aspect: TextureAspect::All,
-                            //     base_mip_level: 0,
-                            //     mip_level_count: Some(1),
-                            //     base_array_layer: 0,
-                            //     array_layer_count: Some(1),
-                            // });
-                            let texture_view =
-                                texture.create_view(&wgpu::TextureViewDescriptor::default());
-
-                            texture_views.insert(
-                                FRAMEBUFFER_HANDLE,
-                                ManualTextureView::with_default_format(
-                                    texture_view.into(),
-                                    UVec2 {
-                                        x: base_layer.framebuffer_width(),
-                                        y: base_layer.framebuffer_height(),
-                                    },
-                                ),
-                            );
-
                             let views: Vec<XrView> = views.iter().map(|view| view.into()).collect();
 
+                            // Whether each view is a secondary view and the order of its camera. All views share a target,
+                            // so spawned cameras clear it like update_xr_clear_color does from their first frame on.
+                            let mut secondary_view_count = 0;
+                            let view_orders: Vec<(bool, isize)> = views.iter().enumerate().map(|(i, view)| {
+                                // Not available in web_sys yet.
+                                let secondary = js_sys::Reflect::get(view, &"isFirstPersonObserver".into())
+                                    .ok()
+                                    .and_then(|value| value.as_bool())
+                                    .unwrap_or(false);
+
+                                let order = if secondary {
+                                    secondary_view_count += 1;
+                                    SECONDARY_VIEW_CAMERA_ORDER + secondary_view_count - 1
+                                } else if inline && view.eye() == web_sys::XrEye::None {
+                                    INLINE_CAMERA_ORDER + i as isize
+                                } else {
+                                    i as isize
+                                };
+                                (secondary, order)
+                            }).collect();
+                            let first_order = view_orders
+                                .iter()
+                                .filter(|(secondary, _)| !secondary || secondary_views_settings.enabled)
+                                .map(|(_, order)| *order)
+                                .min();
+                            let passthrough = is_passthrough(blend_mode.as_deref());
+                            let spawn_clear_color = |order: isize| view_clear_color(&clear_color, passthrough, Some(order) == first_order);
+
                             let mut eyes_left = eyes_left.iter_mut();
                             let mut eyes_right = eyes_right.iter_mut();
                             let mut windows = windows.iter_mut();
+                            let mut secondary_views = secondary_views.iter_mut();
 
                             let mut eye_left_index = 0;
                             let mut eye_right_index = 0;
                             let mut window_index = 0;
+                            let mut secondary_view_index = 0;
 
                             for (i, view) in views.iter().enumerate() {
+                                let (secondary, order) = view_orders[i];
+
+                                if secondary && !secondary_views_settings.enabled {
+                                    continue;
+                                }
+
+                                // Has to be requested every frame before the viewport is queried.
+                                if !inline {
+                                    let scale = if secondary {
+                                        secondary_views_settings.viewport_scale
+                                    } else if viewport_scale.automatic {
+                                        // The browser recommends a lower scale when it knows the device can't keep up.
+                                        viewport_scale.scale.min(view.recommended_viewport_scale().unwrap_or(1.0) as f32)
+                                    } else {
+                                        viewport_scale.scale
+                                    };
+                                    view.request_viewport_scale(Some(scale.clamp(0.0, 1.0) as f64));
+                                }
                                 let viewport = base_layer.get_viewport(view).unwrap();
+
+                                if secondary {
+                                    let secondary_viewport = Viewport {
+                                        physical_position: UVec2 {
+                                            x: viewport.x() as u32,
+                                            y: viewport.y() as u32,
+                                        },
+                                        physical_size: UVec2 {
+                                            x: viewport.width() as u32,
+                                            y: viewport.height() as u32,
+                                        },
+                                        ..default()
+                                    };
+                                    if let Some((
+                                        _,
+                                        mut transform,
+                                        mut camera,
+                                        mut projection,
+                                        mut active,
+                                    )) = secondary_views.next()
+                                    {
+                                        *transform = view_transform(&head_transform, view);
+                                        camera.viewport = Some(secondary_viewport);
+                                        camera.is_active = true;
+                                        active.0 = true;
+                                        projection.update_matrix(view.projection_matrix());
+                                    } else {
+                                        let mut secondary_view = commands
+                                            .spawn((
+                                                Camera3dBundle {
+                                                    camera: Camera {
+                                                        viewport: Some(secondary_viewport),
+                                                        target: target.clone(),
+                                                        order,
+                                                        ..default()
+                                                    },
+                                                    camera_3d: Camera3d {
+                                                        clear_color: spawn_clear_color(order),
+                                                        ..default()
+                                                    },
+                                                    transform: view_transform(&head_transform, view),
+                                                    ..default()
+                                                },
+                                                WebXrProjection::from(view.projection_matrix()),
+                                                XrSecondaryView(secondary_view_index),
+                                                XrLocal,
+                                                XrActive(true),
+                                            ));
+                                        secondary_view.remove::<Projection>();
+                                        let secondary_view = secondary_view.id();
+
+                                        commands.entity(head).add_child(secondary_view);
+                                    }
+                                    secondary_view_index += 1;
+                                    continue;
+                                }
+
                                 match view.eye() {
                                     web_sys::XrEye::Left => {
                                         if let Some((
@@ -177,10 +329,7 @@ pub(crate) fn update_xr_cameras(
                                             mut active,
                                         )) = eyes_left.next()
                                         {
-                                            transform.translation =
-                                                dom_point_to_vec3(&view.transform().position());
-                                            transform.rotation =
-                                                dom_point_to_quat(&view.transform().orientation());
+                                            *transform = view_transform(&head_transform, view);
                                             camera.viewport = Some(Viewport {
                                                 physical_position: UVec2 {
                                                     x: viewport.x() as u32,
@@ -207,23 +356,15 @@ pub(crate) fn update_xr_cameras(
                                                                 ..default()
                                                             }),
                                                             target: bevy::render::camera::RenderTarget::TextureView(FRAMEBUFFER_HANDLE),
-                                                            order: i as isize,
+                                                            order,
                                                             
                                                             ..default()
                                                         },
                                                         camera_3d: Camera3d{
-                                                            clear_color: ClearColorConfig::Custom(Color::NONE),
+                                                            clear_color: spawn_clear_color(order),
                                                             ..default()
                                                         },
-                                                        transform: Transform {
-                                                        translation: dom_point_to_vec3(
-                                                            &view.transform().position(),
-                                                        ),
-                                                        rotation: dom_point_to_quat(
-                                                            &view.transform().orientation(),
-                                                        ),
-                                                        ..Default::default()
-                                                    },
+                                                        transform: view_transform(&head_transform, view),
                                                         ..default()
                                                     },
                                                     WebXrProjection::from(view.projection_matrix()),
@@ -238,7 +379,7 @@ pub(crate) fn update_xr_cameras(
                                             eye.log_components();
                                             let eye = eye.id();
 
-                                            commands.entity(origin.single()).add_child(eye);
+                                            commands.entity(head).add_child(eye);
                                         }
                                         eye_left_index += 1;
                                     }
@@ -251,10 +392,7 @@ pub(crate) fn update_xr_cameras(
                                             mut active,
                                         )) = eyes_right.next()
                                         {
-                                            transform.translation =
-                                                dom_point_to_vec3(&view.transform().position());
-                                            transform.rotation =
-                                                dom_point_to_quat(&view.transform().orientation());
+                                            *transform = view_transform(&head_transform, view);
                                             camera.viewport = Some(Viewport {
                                                 physical_position: UVec2 {
                                                     x: viewport.x() as u32,
@@ -281,22 +419,14 @@ pub(crate) fn update_xr_cameras(
                                                                 ..default()
                                                             }),
                                                             target: bevy::render::camera::RenderTarget::TextureView(FRAMEBUFFER_HANDLE),
-                                                            order: i as isize,
+                                                            order,
                                                             ..default()
                                                         },
                                                         camera_3d: Camera3d{
-                                                            clear_color: ClearColorConfig::None,
+                                                            clear_color: spawn_clear_color(order),
                                                             ..default()
                                                         },
-                                                        transform: Transform {
-                                                        translation: dom_point_to_vec3(
-                                                            &view.transform().position(),
-                                                        ),
-                                                        rotation: dom_point_to_quat(
-                                                            &view.transform().orientation(),
-                                                        ),
-                                                        ..Default::default()
-                                                    },
+                                                        transform: view_transform(&head_transform, view),
                                                         ..default()
                                                     },
                                                     WebXrProjection::from(view.projection_matrix()),
@@ -311,79 +441,74 @@ pub(crate) fn update_xr_cameras(
                                             eye.log_components();
                                             let eye = eye.id();
 
-                                            commands.entity(origin.single()).add_child(eye);
+                                            commands.entity(head).add_child(eye);
                                         }
                                         eye_right_index += 1;
                                     }
                                     web_sys::XrEye::None => {
+                                        let window_viewport = (!inline).then(|| Viewport {
+                                            physical_position: UVec2 {
+                                                x: viewport.x() as u32,
+                                                y: viewport.y() as u32,
+                                            },
+                                            physical_size: UVec2 {
+                                                x: viewport.width() as u32,
+                                                y: viewport.height() as u32,
+                                            },
+                                            ..default()
+                                        });
                                         if let Some((
-                                            _,
+                                            entity,
                                             mut transform,
                                             mut camera,
                                             mut projection,
                                             mut active,
+                                            flipped,
                                         )) = windows.next()
                                         {
-                                            transform.translation =
-                                                dom_point_to_vec3(&view.transform().position());
-                                            transform.rotation =
-                                                dom_point_to_quat(&view.transform().orientation());
-                                            camera.viewport = Some(Viewport {
-                                                physical_position: UVec2 {
-                                                    x: viewport.x() as u32,
-                                                    y: viewport.y() as u32,
-                                                },
-                                                physical_size: UVec2 {
-                                                    x: viewport.width() as u32,
-                                                    y: viewport.height() as u32,
-                                                },
-                                                ..default()
-                                            });
+                                            *transform = view_transform(&head_transform, view);
+                                            camera.viewport = window_viewport;
+                                            camera.target = target.clone();
                                             camera.is_active = true;
                                             active.0 = true;
                                             projection.update_matrix(view.projection_matrix());
+                                            if inline && flipped {
+                                                commands.entity(entity).remove::<FlipView>();
+                                            } else if !inline && !flipped {
+                                                commands.entity(entity).insert(FlipView::Y);
+                                            }
                                         } else {
                                             let mut window = commands
                                                 .spawn((
                                                     Camera3dBundle {
                                                         // TODO: What is msaa_writeback?
                                                         camera: Camera {
-                                                            viewport: Some(Viewport{
-                                                                physical_position: UVec2 { x: viewport.x() as u32, y: viewport.y() as u32 },
-                                                                physical_size: UVec2 { x: viewport.width() as u32, y: viewport.height() as u32},
-                                                                ..default()
-                                                            }),
-                                                            target: bevy::render::camera::RenderTarget::TextureView(FRAMEBUFFER_HANDLE),
-                                                            order: i as isize,
+                                                            viewport: window_viewport,
+                                                            target: target.clone(),
+                                                            order,
                                                             
                                                             ..default()
                                                         },
                                                         camera_3d: Camera3d{
-                                                            clear_color: ClearColorConfig::Custom(Color::NONE),
+                                                            clear_color: spawn_clear_color(order),
                                                             ..default()
                                                         },
-                                                        transform: Transform {
-                                                        translation: dom_point_to_vec3(
-                                                            &view.transform().position(),
-                                                        ),
-                                                        rotation: dom_point_to_quat(
-                                                            &view.transform().orientation(),
-                                                        ),
-                                                        ..Default::default()
-                                                    },
+                                                        transform: view_transform(&head_transform, view),
                                                         ..default()
                                                     },
                                                     WebXrProjection::from(view.projection_matrix()),
-                                                    FlipView::Y,
                                                     XrWindow(window_index),
                                                     XrLocal,
                                                     XrActive(true),
                                                 ));
+                                            if !inline {
+                                                window.insert(FlipView::Y);
+                                            }
                                             window.remove::<Projection>();
                                             window.log_components();
                                             let window = window.id();
 
-                                            commands.entity(origin.single()).add_child(window);
+                                            commands.entity(head).add_child(window);
                                         }
                                         window_index += 1;
                                     }
@@ -401,7 +526,12 @@ pub(crate) fn update_xr_cameras(
                                 active.0 = false;
                             }
 
-                            for (_, _, mut camera, _, mut active) in windows {
+                            for (_, _, mut camera, _, mut active, _) in windows {
+                                camera.is_active = false;
+                                active.0 = false;
+                            }
+
+                            for (_, _, mut camera, _, mut active) in secondary_views {
                                 camera.is_active = false;
                                 active.0 = false;
                             }
@@ -419,12 +549,9 @@ pub(crate) fn update_xr_cameras(
             } else {
                 warn!("Failed to get webxr viewer pose!");
             }
-        } else {
-            {
-                warn!("Failed to get WebXrFrame!");
-            }
         }
-    } else {
+        // Without a WebXrFrame there is no running session and the window is rendering.
+    } else if xr_frame.is_some() {
         warn!("No XrOrigin!");
     }
 
@@ -439,8 +566,137 @@ pub(crate) fn update_xr_cameras(
         active.0 = false;
     }
 
-    for (_, _, mut camera, _, mut active) in windows.iter_mut() {
+    for (_, _, mut camera, _, mut active, _) in windows.iter_mut() {
+        camera.is_active = false;
+        active.0 = false;
+    }
+
+    for (_, _, mut camera, _, mut active) in secondary_views.iter_mut() {
         camera.is_active = false;
         active.0 = false;
     }
+
+    for (_, _, mut active) in heads.iter_mut() {
+        active.0 = false;
+    }
+}
+
+///
+/// The views are posed in the reference space, but the cameras are children of the head.
+///
+fn view_transform(head_transform: &Transform, view: &XrView) -> Transform {
+    let inverse_rotation = head_transform.rotation.inverse();
+    Transform {
+        translation: inverse_rotation * (dom_point_to_vec3(&view.transform().position()) - head_transform.translation),
+        rotation: inverse_rotation * dom_point_to_quat(&view.transform().orientation()),
+        ..default()
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn insert_framebuffer_texture_view(
+    framebuffer: web_sys::WebGlFramebuffer,
+    format: wgpu::TextureFormat,
+    base_layer: &XrWebGlLayer,
+    render_device: &RenderDevice,
+    texture_views: &mut ManualTextureViews,
+) {
+    use bevy::render::camera::ManualTextureView;
+    use wgpu::TextureUsages;
+
+    let texture = unsafe {
+        render_device
+            .wgpu_device()
+            .create_texture_from_hal::<wgpu_hal::gles::Api>(
+                wgpu_hal::gles::Texture {
+                    inner:
+                        wgpu_hal::gles::TextureInner::ExternalFramebuffer {
+                            inner: framebuffer,
+                        },
+                    mip_level_count: 1,
+                    array_layer_count: 1,
+                    format,
+                    format_desc: wgpu_hal::gles::TextureFormatDesc {
+                        internal: if format.is_srgb() { glow::SRGB8_ALPHA8 } else { glow::RGBA },
+                        external: glow::RGBA,
+                        data_type: glow::UNSIGNED_BYTE,
+                    },
+                    copy_size: wgpu_hal::CopyExtent {
+                        width: base_layer.framebuffer_width(),
+                        height: base_layer.framebuffer_height(),
+                        depth: 1,
+                    },
+                    drop_guard: None,
+                    is_cubemap: false,
+                },
+                &wgpu::TextureDescriptor {
+                    label: Some("webxr framebuffer (color)"),
+                    size: wgpu::Extent3d {
+                        width: base_layer.framebuffer_width(),
+                        height: base_layer.framebuffer_height(),
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    view_formats: &[format],
+                    usage: TextureUsages::RENDER_ATTACHMENT
+                        | TextureUsages::TEXTURE_BINDING
+                        | TextureUsages::COPY_SRC,
+                },
+            )
+    };
+
+    // let texture_view = texture.create_view(&wgpu::TextureViewDescriptor {
+    //     label: Some("webxr_framebuffer_color"),
+    //     format: Some(TEXTURE_FORMAT),
+    //     dimension: Some(wgpu::TextureViewDimension::D2),
+    //     aspect: TextureAspect::All,
+    //     base_mip_level: 0,
+    //     mip_level_count: Some(1),
+    //     base_array_layer: 0,
+    //     array_layer_count: Some(1),
+    // });
+    let texture_view =
+        texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    // The cameras' pipelines are specialized for this format, so it has to be the framebuffer's actual format.
+    texture_views.insert(
+        FRAMEBUFFER_HANDLE,
+        ManualTextureView {
+            texture_view: texture_view.into(),
+            size: UVec2 {
+                x: base_layer.framebuffer_width(),
+                y: base_layer.framebuffer_height(),
+            },
+            format,
+        },
+    );
+}
+
+/// Only wgpu's web backend can wrap external framebuffers. Other targets only build the crate, e.g. for unit tests.
+#[cfg(not(target_arch = "wasm32"))]
+fn insert_framebuffer_texture_view(
+    _framebuffer: web_sys::WebGlFramebuffer,
+    _format: wgpu::TextureFormat,
+    _base_layer: &XrWebGlLayer,
+    _render_device: &RenderDevice,
+    _texture_views: &mut ManualTextureViews,
+) {
+    unreachable!("There are no xr framebuffers outside of the web!");
+}
+
+///
+/// Releases the framebuffer texture view of an ended session.
+///
+pub(crate) fn release_framebuffer_texture_view(
+    mut ended: EventReader<WebXrSessionEnded>,
+    mut framebuffer_cache: NonSendMut<XrFramebufferCache>,
+    mut texture_views: ResMut<ManualTextureViews>,
+) {
+    if ended.read().count() > 0 {
+        framebuffer_cache.0 = None;
+        texture_views.remove(&FRAMEBUFFER_HANDLE);
+    }
 }
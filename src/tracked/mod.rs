@@ -2,5 +2,6 @@ pub mod camera;
 pub mod controllers;
 pub mod hands;
 pub mod space;
+pub mod spectator;
 
 // TODO: Handle not having an XrOrigin smoothly. single() -> get_single()
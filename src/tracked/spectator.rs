@@ -0,0 +1,94 @@
+use bevy::{prelude::*, render::camera::RenderTarget, window::WindowRef};
+
+use crate::{state::WebXrSessionState, WebXrSpectator, XrHead, XrMode};
+
+/// The spectator renders into the window after the app's own cameras.
+const SPECTATOR_CAMERA_ORDER: isize = 50;
+
+///
+/// Marks the camera that mirrors an immersive session into the page's window.
+///
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct XrSpectator;
+
+pub(crate) fn update_xr_spectator(
+    settings: Res<WebXrSpectator>,
+    state: Res<State<WebXrSessionState>>,
+    time: Res<Time>,
+    sources: Query<&GlobalTransform, Without<XrSpectator>>,
+    heads: Query<Entity, With<XrHead>>,
+    mut spectators: Query<
+        (
+            &mut Transform,
+            &mut GlobalTransform,
+            &mut Camera,
+            &mut Projection,
+        ),
+        With<XrSpectator>,
+    >,
+    mut commands: Commands,
+) {
+    let immersive = matches!(state.mode(), Some(XrMode::VR) | Some(XrMode::AR));
+
+    let source = settings
+        .target
+        .or_else(|| heads.iter().next())
+        .and_then(|source| sources.get(source).ok())
+        .map(|source| source.compute_transform());
+
+    let Some(source) = source.filter(|_| settings.enabled && immersive) else {
+        for (_, _, mut camera, _) in spectators.iter_mut() {
+            camera.is_active = false;
+        }
+        return;
+    };
+
+    let Ok((mut transform, mut global_transform, mut camera, mut projection)) =
+        spectators.get_single_mut()
+    else {
+        commands.spawn((
+            Camera3dBundle {
+                camera: Camera {
+                    order: SPECTATOR_CAMERA_ORDER,
+                    target: RenderTarget::Window(WindowRef::Primary),
+                    ..default()
+                },
+                projection: Projection::Perspective(PerspectiveProjection {
+                    fov: settings.fov,
+                    ..default()
+                }),
+                transform: source.with_scale(Vec3::ONE),
+                ..default()
+            },
+            XrSpectator,
+        ));
+        return;
+    };
+
+    // Exponential smoothing, so the result doesn't depend on the frame rate.
+    let factor = if settings.smoothing > 0.0 {
+        1.0 - (-time.delta_seconds() / settings.smoothing).exp()
+    } else {
+        1.0
+    };
+
+    // A newly activated spectator starts at the source instead of flying over from its last pose.
+    if !camera.is_active {
+        transform.translation = source.translation;
+        transform.rotation = source.rotation;
+    } else {
+        transform.translation = transform.translation.lerp(source.translation, factor);
+        transform.rotation = transform.rotation.slerp(source.rotation, factor);
+    }
+
+    // The transforms have already been propagated this frame.
+    *global_transform = GlobalTransform::from(*transform);
+
+    camera.is_active = true;
+
+    if let Projection::Perspective(perspective) = projection.as_mut() {
+        if perspective.fov != settings.fov {
+            perspective.fov = settings.fov;
+        }
+    }
+}